@@ -0,0 +1,73 @@
+//! Timing for building the graph and its net index from the testcase netlists.
+//!
+//! Run with `cargo bench --bench graph`. Besides every netlist in `testcases/json`, each one is
+//! also replicated into a larger module, to show how graph construction scales.
+use serde_json::from_slice;
+use std::fs;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use ver2gal::yosys_parser::{Graph, Net, YosysCell, YosysDoc};
+
+const ITERATIONS: u32 = 20;
+const COPIES: u32 = 32;
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn offset_nets(cell: &mut YosysCell, offset: u32) {
+    let conns = match cell {
+        YosysCell::Sop(s) | YosysCell::OneSop(s) => &mut s.connections,
+        YosysCell::Input(i) => &mut i.connections,
+        YosysCell::OLMC(o) => &mut o.connections,
+    };
+    for net in conns.values_mut().flatten() {
+        if let Net::N(n) = net {
+            *n += offset;
+        }
+    }
+}
+
+/// Copy every cell of every module `copies` times, using a distinct range of nets for each copy.
+fn replicate(doc: &YosysDoc, copies: u32) -> YosysDoc {
+    let mut doc = doc.clone();
+    for module in doc.modules.values_mut() {
+        let cells: Vec<_> = module.cells.drain().collect();
+        for copy in 0..copies {
+            for (name, cell) in &cells {
+                let mut cell = cell.clone();
+                offset_nets(&mut cell, copy * 100_000);
+                module.cells.insert(format!("{name}_{copy}"), cell);
+            }
+        }
+    }
+    doc
+}
+
+fn main() {
+    for entry in fs::read_dir("testcases/json").expect("testcases/json should exist") {
+        let path = entry.unwrap().path();
+        let f = fs::read(&path).unwrap();
+        let doc: YosysDoc = from_slice(f.as_slice()).unwrap();
+        let big = replicate(&doc, COPIES);
+
+        for (label, doc) in [("original", &doc), ("replicated", &big)] {
+            let mut g = Graph::from(doc.clone());
+            let nodes = g.nodelist.len();
+            let from = time(|| {
+                black_box(Graph::from(doc.clone()));
+            });
+            let adj = time(|| g.generate_adjacency());
+            println!(
+                "{} ({label}, {nodes} nodes): Graph::from {:?}, generate_adjacency {:?}",
+                path.display(),
+                from,
+                adj
+            );
+        }
+    }
+}
@@ -0,0 +1,404 @@
+//! Write a fitted design as CUPL source, and check the result.
+//!
+//! The file has the usual header block, a `PIN` declaration per used pin, `FIELD` statements
+//! for multi-bit ports, and one equation per OLMC with `.d` for registered outputs and `.oe`
+//! for output enables. `check` parses the equations back and compares them against the
+//! blueprint, so we know the exported source programs the same logic.
+
+use crate::fitter::{chip_to_olmc_offset, Fit};
+use crate::terms::{self, is_true, term_expr, Syntax};
+use crate::yosys_parser::{group_bits, split_bit_name, Graph};
+use galette::blueprint::{Active, PinMode};
+use galette::chips::Chip;
+use galette::gal::Term;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Make a CUPL variable name. Bus bits lose their brackets, so `io[3]` becomes `io3`.
+fn cupl_name(name: &str) -> String {
+    let (base, idx) = match split_bit_name(name) {
+        Some((base, idx)) => (base, Some(idx)),
+        None => (name, None),
+    };
+    let mut out: String = base
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>()
+        .trim_matches('_')
+        .to_string();
+    if !out.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        out.insert(0, 'S');
+    }
+    if let Some(idx) = idx {
+        write!(out, "{idx}").unwrap();
+    }
+    out
+}
+
+fn device(chip: Chip) -> &'static str {
+    match chip {
+        Chip::GAL16V8 => "g16v8",
+        Chip::GAL22V10 => "g22v10",
+        _ => panic!("unsupported chip"),
+    }
+}
+
+/// Sums of products, one product per line.
+const SYNTAX: Syntax = Syntax {
+    zero: "'b'0",
+    one: "'b'1",
+    unknown: "'b'x",
+    not: "!",
+    and: " & ",
+    or: "\n    # ",
+    bracket: false,
+};
+
+/// Generate CUPL source equivalent to the fitted design.
+pub fn to_cupl(fit: &Fit, graph: &Graph) -> String {
+    let chip = fit.chip;
+    // CUPL names are case insensitive.
+    let names = terms::pin_names(fit, graph, cupl_name, &[], true);
+    let literals = terms::plain(&names);
+    let module = graph.module_name();
+
+    let mut out = String::new();
+    writeln!(out, "Name     {};", cupl_name(&module)).unwrap();
+    writeln!(out, "PartNo   00;").unwrap();
+    writeln!(out, "Date     00/00/00;").unwrap();
+    writeln!(out, "Revision 01;").unwrap();
+    writeln!(out, "Designer ver2gal;").unwrap();
+    writeln!(out, "Company  None;").unwrap();
+    writeln!(out, "Assembly None;").unwrap();
+    writeln!(out, "Location None;").unwrap();
+    writeln!(out, "Device   {};", device(chip)).unwrap();
+    writeln!(out).unwrap();
+
+    for (pin, name) in &names {
+        writeln!(out, "PIN {pin} = {name};").unwrap();
+    }
+
+    // buses whose bits all made it onto pins.
+    let buses = group_bits(graph.ports.iter().map(|p| (p.name.clone(), ())).collect());
+    let mut fields: Vec<String> = buses
+        .into_iter()
+        .filter(|(base, bits)| bits.len() > 1 || !graph.ports.iter().any(|p| &p.name == base))
+        .filter_map(|(base, bits)| {
+            // fields list the MSB first, which is index 0 of an upto port.
+            let mut order: Vec<usize> = (0..bits.len()).rev().collect();
+            if graph.upto.contains(&base) {
+                order.reverse();
+            }
+            let members: Option<Vec<&String>> = order
+                .into_iter()
+                .map(|i| {
+                    let signal = format!("{base}[{i}]");
+                    let pin = graph
+                        .ports
+                        .iter()
+                        .find(|p| p.name == signal)
+                        .and_then(|p| p.lookup(&fit.pcf))?;
+                    names.get(&pin)
+                })
+                .collect();
+            let members: Vec<&str> = members?.into_iter().map(|m| m.as_str()).collect();
+            Some(format!("FIELD {} = [{}];", cupl_name(&base), members.join(", ")))
+        })
+        .collect();
+    fields.sort();
+    if !fields.is_empty() {
+        writeln!(out).unwrap();
+        for field in fields {
+            writeln!(out, "{field}").unwrap();
+        }
+    }
+
+    let offset = chip_to_olmc_offset(&chip);
+    for (row, olmc) in fit.blueprint.olmcs.iter().enumerate() {
+        let Some((mode, term)) = &olmc.output else {
+            continue;
+        };
+        let pin = (row + offset) as u32;
+        let name = &names[&pin];
+        let neg = if olmc.active == Active::Low { "!" } else { "" };
+        let ext = if *mode == PinMode::Registered { ".d" } else { "" };
+        writeln!(out).unwrap();
+        writeln!(out, "{neg}{name}{ext} = {};", term_expr(term, &literals, &SYNTAX)).unwrap();
+        if let Some(en) = olmc.tri_con.as_ref().filter(|en| !is_true(en)) {
+            writeln!(out, "{name}.oe = {};", term_expr(en, &literals, &SYNTAX)).unwrap();
+        }
+    }
+    out
+}
+
+/// A parsed CUPL expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Const(bool),
+    Var(String),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, vars: &HashMap<String, bool>) -> Result<bool, String> {
+        Ok(match self {
+            Expr::Const(b) => *b,
+            Expr::Var(v) => *vars.get(v).ok_or(format!("unknown variable {v}"))?,
+            Expr::Not(e) => !e.eval(vars)?,
+            Expr::And(es) => {
+                let mut v = true;
+                for e in es {
+                    v &= e.eval(vars)?;
+                }
+                v
+            }
+            Expr::Or(es) => {
+                let mut v = false;
+                for e in es {
+                    v |= e.eval(vars)?;
+                }
+                v
+            }
+        })
+    }
+}
+
+/// A small recursive descent parser for the expressions `to_cupl` writes:
+/// `!` binds tightest, then `&`, then `#`.
+struct Parser<'a> {
+    tokens: Vec<&'a str>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(src: &'a str) -> Self {
+        let mut tokens = Vec::new();
+        let mut start = None;
+        for (i, c) in src.char_indices() {
+            let word = c.is_ascii_alphanumeric() || c == '_' || c == '\'';
+            match (word, start) {
+                (true, None) => start = Some(i),
+                (false, Some(s)) => {
+                    tokens.push(&src[s..i]);
+                    start = None;
+                }
+                _ => {}
+            }
+            if !word && !c.is_whitespace() {
+                tokens.push(&src[i..i + c.len_utf8()]);
+            }
+        }
+        if let Some(s) = start {
+            tokens.push(&src[s..]);
+        }
+        Parser { tokens, pos: 0 }
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let tok = self.tokens.get(self.pos).copied();
+        self.pos += 1;
+        tok
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut terms = vec![self.and()?];
+        while self.peek() == Some("#") {
+            self.next();
+            terms.push(self.and()?);
+        }
+        Ok(if terms.len() == 1 { terms.pop().unwrap() } else { Expr::Or(terms) })
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut terms = vec![self.not()?];
+        while self.peek() == Some("&") {
+            self.next();
+            terms.push(self.not()?);
+        }
+        Ok(if terms.len() == 1 { terms.pop().unwrap() } else { Expr::And(terms) })
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some("!") => Ok(Expr::Not(Box::new(self.not()?))),
+            Some("(") => {
+                let e = self.or()?;
+                match self.next() {
+                    Some(")") => Ok(e),
+                    t => Err(format!("expected ), found {t:?}")),
+                }
+            }
+            Some("'b'0") => Ok(Expr::Const(false)),
+            Some("'b'1") => Ok(Expr::Const(true)),
+            Some(v) if v.starts_with(|c: char| c.is_ascii_alphabetic()) => Ok(Expr::Var(v.into())),
+            t => Err(format!("unexpected token {t:?}")),
+        }
+    }
+}
+
+/// One `[!]name[.ext] = expr;` statement.
+struct Equation {
+    name: String,
+    ext: String,
+    negated: bool,
+    expr: Expr,
+}
+
+/// Read the pin declarations and equations out of CUPL source.
+fn parse(src: &str) -> Result<(HashMap<String, u32>, Vec<Equation>), String> {
+    let mut pins = HashMap::new();
+    let mut equations = Vec::new();
+    for statement in src.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+        let Some((lhs, rhs)) = statement.split_once('=') else {
+            continue;
+        };
+        let lhs = lhs.trim();
+        if let Some(pin) = lhs.strip_prefix("PIN ") {
+            let pin = pin.trim().parse().map_err(|_| format!("bad pin number in {lhs}"))?;
+            pins.insert(rhs.trim().to_string(), pin);
+            continue;
+        }
+        if lhs.contains(' ') {
+            // header fields and FIELD statements.
+            continue;
+        }
+        let (negated, lhs) = match lhs.strip_prefix('!') {
+            Some(l) => (true, l),
+            None => (false, lhs),
+        };
+        let (name, ext) = lhs.split_once('.').unwrap_or((lhs, ""));
+        let mut parser = Parser::new(rhs);
+        let expr = parser.or()?;
+        if let Some(t) = parser.peek() {
+            return Err(format!("trailing {t} in equation for {lhs}"));
+        }
+        equations.push(Equation {
+            name: name.to_string(),
+            ext: ext.to_lowercase(),
+            negated,
+            expr,
+        });
+    }
+    Ok((pins, equations))
+}
+
+fn term_value(term: &Term, pins: &HashMap<u32, bool>) -> bool {
+    term.pins
+        .iter()
+        .any(|product| product.iter().all(|p| pins[&(p.pin as u32)] != p.neg))
+}
+
+/// Check that CUPL source computes the same output and enable functions as the fit,
+/// over every input combination (or a sample of them for wide designs).
+pub fn check(fit: &Fit, src: &str) -> Result<(), String> {
+    let (pins, equations) = parse(src)?;
+    let mut inputs: Vec<(&String, u32)> = pins.iter().map(|(n, p)| (n, *p)).collect();
+    inputs.sort_by_key(|(_, p)| *p);
+
+    let vectors: Vec<u64> = if inputs.len() <= 12 {
+        (0..1u64 << inputs.len()).collect()
+    } else {
+        // a fixed pseudo-random sample, so failures can be reproduced.
+        let mut x: u64 = 0x9e37_79b9_7f4a_7c15;
+        (0..4096)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x
+            })
+            .collect()
+    };
+
+    let offset = chip_to_olmc_offset(&fit.chip) as u32;
+    for v in vectors {
+        let mut by_pin: HashMap<u32, bool> =
+            (1..=fit.chip.num_pins() as u32).map(|p| (p, false)).collect();
+        for (i, (_, pin)) in inputs.iter().enumerate() {
+            by_pin.insert(*pin, v >> (i % 64) & 1 == 1);
+        }
+        let by_name: HashMap<String, bool> = inputs
+            .iter()
+            .map(|(name, pin)| ((*name).clone(), by_pin[pin]))
+            .collect();
+
+        for (row, olmc) in fit.blueprint.olmcs.iter().enumerate() {
+            let Some((_, term)) = &olmc.output else {
+                continue;
+            };
+            let pin = row as u32 + offset;
+            let name = pins
+                .iter()
+                .find(|(_, p)| **p == pin)
+                .map(|(n, _)| n)
+                .ok_or(format!("no PIN declaration for pin {pin}"))?;
+            let eq = equations
+                .iter()
+                .find(|e| &e.name == name && e.ext != "oe")
+                .ok_or(format!("no equation for {name}"))?;
+            let want = term_value(term, &by_pin) != (olmc.active == Active::Low);
+            let got = eq.expr.eval(&by_name)? != eq.negated;
+            if want != got {
+                return Err(format!("{name} differs from the fit with inputs {by_name:?}"));
+            }
+
+            let want_oe = olmc.tri_con.as_ref().is_none_or(|en| term_value(en, &by_pin));
+            let got_oe = match equations.iter().find(|e| &e.name == name && e.ext == "oe") {
+                Some(eq) => eq.expr.eval(&by_name)? != eq.negated,
+                None => true,
+            };
+            if want_oe != got_oe {
+                return Err(format!("{name}.oe differs from the fit with inputs {by_name:?}"));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{hand_netlist, testcase_pcf, writer_fit, TESTCASES};
+
+    fn fit() -> (Fit, Graph) {
+        writer_fit(Chip::GAL22V10)
+    }
+
+    #[test]
+    fn test_to_cupl() {
+        let (fit, g) = fit();
+        let cupl = to_cupl(&fit, &g);
+        assert!(cupl.contains("Device   g22v10;"), "{cupl}");
+        assert!(cupl.contains("PIN 2 = in0;"), "{cupl}");
+        assert!(cupl.contains("FIELD in = [in1, in0];"), "{cupl}");
+        assert!(cupl.contains("q.d = in0 & !in1\n    # q;"), "{cupl}");
+        assert!(cupl.contains("!y = in0 & !in1\n    # q;\ny.oe = in1;"), "{cupl}");
+        check(&fit, &cupl).unwrap();
+    }
+
+    #[test]
+    fn test_check_catches_mismatch() {
+        let (fit, g) = fit();
+        let cupl = to_cupl(&fit, &g).replace("y.oe = in1", "y.oe = !in1");
+        let err = check(&fit, &cupl).unwrap_err();
+        assert!(err.contains("y.oe differs"), "{err}");
+    }
+
+    #[test]
+    fn test_round_trip_testcases() {
+        for (netlist, pcf) in TESTCASES {
+            let g = hand_netlist(netlist);
+            let fit = crate::fitter::fit(&g, &testcase_pcf(pcf), Chip::GAL16V8).unwrap();
+            let src = to_cupl(&fit, &g);
+            if let Err(e) = check(&fit, &src) {
+                panic!("{netlist}: {e}\n{src}");
+            }
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! Device profiles for the parts sold as 16V8s and 22V10s.
+//!
+//! Each profile picks the base chip the fitter works with, and records what the part does
+//! differently: extra fuses after the base map, where its signature (UES) row is, how many
+//! fuses a programmer reads back, the pins its own features take over, and the name a
+//! programmer knows it by.
+
+use crate::pcf::PcfFile;
+use galette::chips::Chip;
+use std::ops::Range;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum DeviceError {
+    #[error("Unknown device {0}")]
+    Unknown(String),
+
+    #[error("The {0} has no power-down pin")]
+    NoPowerDown(String),
+
+    #[error("Port {port} is on pin {pin}, which is the {device} power-down pin")]
+    ReservedPin {
+        port: String,
+        pin: u32,
+        device: String,
+    },
+}
+
+/// A pin that powers the part down, once a fuse enables it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowerDown {
+    pub pin: u32,
+    pub fuse: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Device {
+    /// The part number, as printed on the chip.
+    pub name: &'static str,
+    pub chip: Chip,
+    /// Fuses in a JEDEC file for the part.
+    pub fuse_count: usize,
+    /// Fuses in a readback of the part, which can stop short of the ones that can't be read.
+    pub readback_fuse_count: usize,
+    /// The user electronic signature fuses.
+    pub ues: Range<usize>,
+    pub power_down: Option<PowerDown>,
+    /// The name the programmer knows the part by.
+    pub programmer_part: &'static str,
+}
+
+/// Every supported part. The base GAL parts come first, so they are the ones found by fuse
+/// count when a file doesn't say which part it is for.
+pub static DEVICES: [Device; 6] = [
+    Device {
+        name: "GAL16V8",
+        chip: Chip::GAL16V8,
+        fuse_count: 2194,
+        readback_fuse_count: 2194,
+        ues: 2056..2120,
+        power_down: None,
+        programmer_part: "GAL16V8",
+    },
+    Device {
+        name: "ATF16V8B",
+        chip: Chip::GAL16V8,
+        fuse_count: 2194,
+        readback_fuse_count: 2194,
+        ues: 2056..2120,
+        power_down: None,
+        programmer_part: "ATF16V8B",
+    },
+    Device {
+        name: "ATF16V8C",
+        chip: Chip::GAL16V8,
+        fuse_count: 2194,
+        readback_fuse_count: 2194,
+        ues: 2056..2120,
+        power_down: None,
+        programmer_part: "ATF16V8C",
+    },
+    Device {
+        name: "GAL22V10",
+        chip: Chip::GAL22V10,
+        fuse_count: 5892,
+        readback_fuse_count: 5892,
+        ues: 5828..5892,
+        power_down: None,
+        programmer_part: "GAL22V10",
+    },
+    // the Atmel ATF22V10C and ATF22LV10C datasheets give both parts the GAL22V10 fuse map,
+    // 64-bit UES included, plus a power-down enable one fuse past its end that isn't read back.
+    Device {
+        name: "ATF22V10C",
+        chip: Chip::GAL22V10,
+        fuse_count: 5893,
+        readback_fuse_count: 5892,
+        ues: 5828..5892,
+        power_down: Some(PowerDown { pin: 4, fuse: 5892 }),
+        programmer_part: "ATF22V10C",
+    },
+    Device {
+        name: "ATF22LV10",
+        chip: Chip::GAL22V10,
+        fuse_count: 5893,
+        readback_fuse_count: 5892,
+        ues: 5828..5892,
+        power_down: Some(PowerDown { pin: 4, fuse: 5892 }),
+        programmer_part: "ATF22LV10C",
+    },
+];
+
+/// Find a device by name, ignoring case.
+pub fn lookup(name: &str) -> Result<&'static Device, DeviceError> {
+    DEVICES
+        .iter()
+        .find(|d| d.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| DeviceError::Unknown(name.to_string()))
+}
+
+/// The base part for a chip.
+pub fn base(chip: Chip) -> &'static Device {
+    DEVICES
+        .iter()
+        .find(|d| d.chip == chip)
+        .expect("every chip has a base part")
+}
+
+/// The first device whose files have this many fuses.
+pub fn for_fuse_count(fuse_count: usize) -> Option<&'static Device> {
+    DEVICES.iter().find(|d| d.fuse_count == fuse_count)
+}
+
+/// Every device whose files or readbacks have this many fuses.
+pub fn for_any_fuse_count(fuse_count: usize) -> Vec<&'static Device> {
+    DEVICES
+        .iter()
+        .filter(|d| d.fuse_count == fuse_count || d.readback_fuse_count == fuse_count)
+        .collect()
+}
+
+impl Device {
+    /// The fuses that come after the base chip's map, with power-down enabled or not.
+    pub fn extra_fuses(&self, power_down: bool) -> Result<Vec<bool>, DeviceError> {
+        let first = base(self.chip).fuse_count;
+        let mut fuses = vec![false; self.fuse_count - first];
+        if power_down {
+            let pd = self
+                .power_down
+                .ok_or_else(|| DeviceError::NoPowerDown(self.name.to_string()))?;
+            fuses[pd.fuse - first] = true;
+        }
+        Ok(fuses)
+    }
+
+    /// Check that no port is constrained to a pin the part needs for itself.
+    pub fn check_pins(&self, pcf: &PcfFile, power_down: bool) -> Result<(), DeviceError> {
+        let Some(pd) = self.power_down.filter(|_| power_down) else {
+            return Ok(());
+        };
+        match pcf.ports_on(pd.pin).first() {
+            Some(port) => Err(DeviceError::ReservedPin {
+                port: port.to_string(),
+                pin: pd.pin,
+                device: self.name.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pcf::parse_pcf;
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(lookup("atf22v10c").unwrap().fuse_count, 5893);
+        assert_eq!(base(Chip::GAL22V10).name, "GAL22V10");
+        assert_eq!(for_fuse_count(2194).unwrap().name, "GAL16V8");
+        let names: Vec<&str> = for_any_fuse_count(5892).iter().map(|d| d.name).collect();
+        assert_eq!(names, ["GAL22V10", "ATF22V10C", "ATF22LV10"]);
+        assert!(for_any_fuse_count(5893).iter().all(|d| d.power_down.is_some()));
+        assert_eq!(
+            lookup("GAL20V8").unwrap_err(),
+            DeviceError::Unknown("GAL20V8".to_string())
+        );
+    }
+
+    #[test]
+    fn test_power_down() {
+        let atf = lookup("ATF22V10C").unwrap();
+        assert_eq!(atf.extra_fuses(false).unwrap(), [false]);
+        assert_eq!(atf.extra_fuses(true).unwrap(), [true]);
+        assert_eq!(
+            lookup("ATF16V8B").unwrap().extra_fuses(true),
+            Err(DeviceError::NoPowerDown("ATF16V8B".to_string()))
+        );
+
+        let pcf = parse_pcf("set_io a 4\nset_io y 23");
+        assert!(atf.check_pins(&pcf, false).is_ok());
+        assert_eq!(
+            atf.check_pins(&pcf, true),
+            Err(DeviceError::ReservedPin {
+                port: "a".to_string(),
+                pin: 4,
+                device: "ATF22V10C".to_string(),
+            })
+        );
+    }
+}
@@ -0,0 +1,866 @@
+//! Prove that a fuse map does what the netlist it was built from does.
+//!
+//! The netlist and the fuse map are both unrolled over clock cycles and encoded as clauses,
+//! and outputs are matched up by their PCF pin. Bounded model checking from power-up looks
+//! for a mismatch, and k-induction proves there is none. Registers power up as the chip's
+//! do: cleared, so registered outputs read high, except for the 22V10's non-inverted ones.
+//! Netlist registers power up at their `init` value, or as the chip does if they have none.
+//!
+//! The fuse map is read as the models in `models/` read it, except that combinational
+//! feedback comes from the pin, so a disabled output can be driven from outside.
+
+use crate::fitter::power_up_level;
+use crate::jedec::{chip_for_fuse_count, GAL16V8_COLUMNS, GAL22V10_COLUMNS, GAL22V10_PRODUCTS};
+use crate::pcf::PcfFile;
+use crate::sat::{Lit, Solver};
+use crate::sim::PinState;
+use crate::sop::{Cover, Literal};
+use crate::yosys_parser::{Graph, Net, Node, NodeIdx, PortDirection};
+use galette::chips::Chip;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum EquivError {
+    #[error("No chip has {0} fuses")]
+    UnknownFuseCount(usize),
+
+    #[error("Unsupported fuse map: {0}")]
+    Unsupported(String),
+
+    #[error("Port {0} has no pin in the constraints")]
+    NoPin(String),
+
+    #[error("Port {0} is not driven by an OLMC")]
+    NotOlmc(String),
+
+    #[error("Port {port} is on pin {pin}, which has no OLMC")]
+    NoOlmcPin { port: String, pin: u32 },
+
+    #[error("Port {port} is on pin {pin}, but the {chip} has no such pin")]
+    PinOutOfRange {
+        port: String,
+        pin: u32,
+        chip: String,
+    },
+
+    #[error("Combinational loop through net {0}")]
+    NetLoop(String),
+
+    #[error("Combinational loop through pin {0} in the fuse map")]
+    FuseLoop(u32),
+
+    #[error("The SAT solver gave up after {0} conflicts")]
+    GaveUp(u64),
+}
+
+/// Conflicts each SAT search may take before the check gives up on it.
+const CONFLICT_BUDGET: u64 = 200_000;
+
+fn solve(s: &mut Solver) -> Result<bool, EquivError> {
+    s.solve_within(CONFLICT_BUDGET)
+        .ok_or(EquivError::GaveUp(CONFLICT_BUDGET))
+}
+
+/// A product term over the array inputs, as (input, negated) pairs.
+type Product = Vec<(usize, bool)>;
+
+/// Where an array input comes from.
+#[derive(Debug, Clone, Copy)]
+enum Signal {
+    Pin(u32),
+    Feedback(usize),
+}
+
+/// What the fuses make an OLMC do.
+#[derive(Debug)]
+struct ChipOlmc {
+    pin: u32,
+    sum: Vec<Product>,
+    /// The output enable products, or `None` if the enable is pin 11.
+    oe: Option<Vec<Product>>,
+    xor: bool,
+    registered: bool,
+    /// Registered feedback is inverted by the XOR fuse, as on the 22V10.
+    fb_xor: bool,
+    /// The register's contents at power-up.
+    power_up: bool,
+}
+
+#[derive(Debug)]
+struct ChipModel {
+    signals: Vec<Signal>,
+    olmcs: Vec<ChipOlmc>,
+}
+
+/// Read a product row, or `None` if it can never be true.
+fn decode_product(fuses: &[bool], start: usize, inputs: usize) -> Option<Product> {
+    let mut product = Vec::new();
+    for s in 0..inputs {
+        // an intact (zero) fuse connects the literal.
+        match (fuses[start + 2 * s], fuses[start + 2 * s + 1]) {
+            (false, false) => return None,
+            (false, true) => product.push((s, false)),
+            (true, false) => product.push((s, true)),
+            (true, true) => {}
+        }
+    }
+    Some(product)
+}
+
+impl ChipModel {
+    fn decode(chip: Chip, fuses: &[bool]) -> Result<ChipModel, EquivError> {
+        let (columns, first_olmc_pin): (&[u32], u32) = match chip {
+            Chip::GAL16V8 => (&GAL16V8_COLUMNS, 19),
+            Chip::GAL22V10 => (&GAL22V10_COLUMNS, 23),
+            _ => return Err(EquivError::Unsupported(chip.name().to_string())),
+        };
+        let signals: Vec<Signal> = columns
+            .iter()
+            .map(|p| match chip.pin_to_olmc(*p as usize) {
+                Some(_) => Signal::Feedback((first_olmc_pin - p) as usize),
+                None => Signal::Pin(*p),
+            })
+            .collect();
+        let inputs = signals.len();
+
+        let mut olmcs = Vec::new();
+        match chip {
+            Chip::GAL16V8 => {
+                if fuses[2192] || !fuses[2193] {
+                    return Err(EquivError::Unsupported(
+                        "the GAL16V8 is not in registered mode".to_string(),
+                    ));
+                }
+                for i in 0..8 {
+                    let rows: Vec<Option<Product>> = (8 * i..8 * i + 8)
+                        .map(|row| {
+                            fuses[2128 + row]
+                                .then(|| decode_product(fuses, row * 32, inputs))
+                                .flatten()
+                        })
+                        .collect();
+                    let combinatorial = fuses[2120 + i];
+                    let (oe, sum) = if combinatorial {
+                        (Some(rows[0].iter().cloned().collect()), &rows[1..])
+                    } else {
+                        (None, &rows[..])
+                    };
+                    olmcs.push(ChipOlmc {
+                        pin: 19 - i as u32,
+                        sum: sum.iter().flatten().cloned().collect(),
+                        oe,
+                        xor: fuses[2048 + i],
+                        registered: !combinatorial,
+                        fb_xor: false,
+                        power_up: false,
+                    });
+                }
+            }
+            _ => {
+                let mut row = 1;
+                for (i, products) in GAL22V10_PRODUCTS.iter().enumerate() {
+                    let oe = decode_product(fuses, row * 44, inputs);
+                    let sum = (row + 1..=row + products)
+                        .filter_map(|r| decode_product(fuses, r * 44, inputs))
+                        .collect();
+                    row += products + 1;
+                    olmcs.push(ChipOlmc {
+                        pin: 23 - i as u32,
+                        sum,
+                        oe: Some(oe.into_iter().collect()),
+                        xor: fuses[5808 + 2 * i],
+                        registered: !fuses[5809 + 2 * i],
+                        fb_xor: true,
+                        // the register clears, but the XOR after it is folded in here.
+                        power_up: fuses[5808 + 2 * i],
+                    });
+                }
+            }
+        }
+        Ok(ChipModel { signals, olmcs })
+    }
+
+    fn olmc_at(&self, pin: u32) -> Option<usize> {
+        self.olmcs.iter().position(|o| o.pin == pin)
+    }
+}
+
+/// One clock cycle of the fuse map.
+struct ChipFrame<'a> {
+    model: &'a ChipModel,
+    pins: &'a BTreeMap<u32, Lit>,
+    regs: &'a [Lit],
+    oe_n: Lit,
+    drives: Vec<Option<(Lit, Lit)>>,
+    outs: Vec<Option<Lit>>,
+    visiting: Vec<bool>,
+    driving: Vec<bool>,
+}
+
+impl ChipFrame<'_> {
+    fn sum(&mut self, s: &mut Solver, products: &[Product]) -> Result<Lit, EquivError> {
+        let mut terms = Vec::new();
+        for product in products {
+            let mut lits = Vec::new();
+            for (input, neg) in product {
+                let lit = self.signal(s, *input)?;
+                lits.push(if *neg { !lit } else { lit });
+            }
+            terms.push(s.and(&lits));
+        }
+        Ok(s.or(&terms))
+    }
+
+    fn signal(&mut self, s: &mut Solver, input: usize) -> Result<Lit, EquivError> {
+        match self.model.signals[input] {
+            Signal::Pin(p) => Ok(self.pins[&p]),
+            Signal::Feedback(i) => {
+                let olmc = &self.model.olmcs[i];
+                if olmc.registered {
+                    let q = !self.regs[i];
+                    Ok(if olmc.fb_xor && olmc.xor { !q } else { q })
+                } else {
+                    let (en, value) = self.drive(s, i)?;
+                    let pin = self.pins[&olmc.pin];
+                    Ok(s.ite(en, value, pin))
+                }
+            }
+        }
+    }
+
+    /// The value the OLMC feeds its register, or drives through the pin when combinational.
+    fn out(&mut self, s: &mut Solver, i: usize) -> Result<Lit, EquivError> {
+        if let Some(lit) = self.outs[i] {
+            return Ok(lit);
+        }
+        if self.visiting[i] {
+            return Err(EquivError::FuseLoop(self.model.olmcs[i].pin));
+        }
+        self.visiting[i] = true;
+        let olmc = &self.model.olmcs[i];
+        let sum = self.sum(s, &olmc.sum)?;
+        let lit = if olmc.xor { !sum } else { sum };
+        self.visiting[i] = false;
+        self.outs[i] = Some(lit);
+        Ok(lit)
+    }
+
+    /// The output enable and the value driven onto the pin.
+    fn drive(&mut self, s: &mut Solver, i: usize) -> Result<(Lit, Lit), EquivError> {
+        if let Some(d) = self.drives[i] {
+            return Ok(d);
+        }
+        if self.driving[i] {
+            return Err(EquivError::FuseLoop(self.model.olmcs[i].pin));
+        }
+        self.driving[i] = true;
+        let olmc = &self.model.olmcs[i];
+        let en = match &olmc.oe {
+            Some(products) => self.sum(s, products)?,
+            None => !self.oe_n,
+        };
+        let value = if olmc.registered {
+            !self.regs[i]
+        } else {
+            !self.out(s, i)?
+        };
+        self.driving[i] = false;
+        self.drives[i] = Some((en, value));
+        Ok((en, value))
+    }
+}
+
+/// One clock cycle of the netlist.
+struct NetFrame<'a> {
+    graph: &'a Graph,
+    pcf: &'a PcfFile,
+    pins: &'a BTreeMap<u32, Lit>,
+    regs: &'a HashMap<usize, Lit>,
+    values: HashMap<Net, Lit>,
+    visiting: HashSet<Net>,
+}
+
+impl NetFrame<'_> {
+    fn net(&mut self, s: &mut Solver, net: &Net) -> Result<Lit, EquivError> {
+        match net {
+            Net::LiteralOne => return Ok(s.constant(true)),
+            // unconnected inputs are treated as 0, as the fitter does.
+            Net::LiteralZero | Net::NotConnected => return Ok(s.constant(false)),
+            Net::N(_) => {}
+        }
+        if let Some(lit) = self.values.get(net) {
+            return Ok(*lit);
+        }
+        if !self.visiting.insert(net.clone()) {
+            return Err(EquivError::NetLoop(self.graph.net_name(net)));
+        }
+        let driver = self.graph.drivers(net).first().map(|c| c.node);
+        let lit = match driver.map(|idx| (idx, &self.graph.nodelist[idx.0])) {
+            Some((_, Node::Input(i))) => self.net(s, &i.connections["A"][0])?,
+            Some((_, Node::Sop(sop))) => {
+                let cover = Cover::from_params(&sop.parameters);
+                let inputs = &sop.connections["A"];
+                let mut terms = Vec::new();
+                for cube in &cover.cubes {
+                    let mut lits = Vec::new();
+                    for (lit, input) in cube.iter().zip(inputs) {
+                        match lit {
+                            Literal::One => lits.push(self.net(s, input)?),
+                            Literal::Zero => lits.push(!self.net(s, input)?),
+                            Literal::DontCare => {}
+                        }
+                    }
+                    terms.push(s.and(&lits));
+                }
+                s.or(&terms)
+            }
+            Some((idx, Node::Olmc(_))) => {
+                let (en, internal) = self.olmc(s, idx)?;
+                match self.graph.find_port(net).and_then(|p| p.lookup(self.pcf)) {
+                    Some(pin) => s.ite(en, internal, self.pins[&pin]),
+                    None => internal,
+                }
+            }
+            None => match self.graph.find_port(net).and_then(|p| p.lookup(self.pcf)) {
+                Some(pin) => self.pins[&pin],
+                None => s.constant(false),
+            },
+        };
+        self.visiting.remove(net);
+        self.values.insert(net.clone(), lit);
+        Ok(lit)
+    }
+
+    /// The output enable and value of an OLMC.
+    fn olmc(&mut self, s: &mut Solver, idx: NodeIdx) -> Result<(Lit, Lit), EquivError> {
+        let Node::Olmc(o) = &self.graph.nodelist[idx.0] else {
+            unreachable!("not an OLMC");
+        };
+        let en = match o.connections.get("E").and_then(|e| e.first()) {
+            None | Some(Net::NotConnected) => s.constant(true),
+            Some(net) => self.net(s, net)?,
+        };
+        let internal = match self.regs.get(&idx.0) {
+            Some(q) => *q,
+            None => self.next(s, idx)?,
+        };
+        Ok((en, internal))
+    }
+
+    /// The value an OLMC's register would load, or its output when combinational.
+    fn next(&mut self, s: &mut Solver, idx: NodeIdx) -> Result<Lit, EquivError> {
+        let Node::Olmc(o) = &self.graph.nodelist[idx.0] else {
+            unreachable!("not an OLMC");
+        };
+        let a = self.net(s, &o.connections["A"][0])?;
+        Ok(if o.parameters.inverted { !a } else { a })
+    }
+}
+
+/// An output compared between the netlist and the fuse map.
+struct Output {
+    name: String,
+    pin: u32,
+    olmc: NodeIdx,
+}
+
+/// The literals of one unrolled cycle.
+struct Frame {
+    pins: BTreeMap<u32, Lit>,
+    /// Netlist enable and value, then fuse map enable and value, for each output.
+    outputs: Vec<[Lit; 4]>,
+    /// The outputs match.
+    outputs_ok: Lit,
+    /// Registers on the same pin hold the same value.
+    regs_ok: Lit,
+}
+
+/// A run of cycles from power-up that shows the difference.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Counterexample {
+    /// The input port values for each cycle.
+    pub inputs: Vec<Vec<(String, bool)>>,
+    /// The netlist and fuse map state of each output, for each cycle.
+    pub outputs: Vec<Vec<(String, PinState, PinState)>>,
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (t, (inputs, outputs)) in self.inputs.iter().zip(&self.outputs).enumerate() {
+            let ins: Vec<String> = inputs
+                .iter()
+                .map(|(name, v)| format!("{name}={}", *v as u8))
+                .collect();
+            let outs: Vec<String> = outputs
+                .iter()
+                .map(|(name, want, _)| format!("{name}={}", want.vector_char()))
+                .collect();
+            write!(f, "cycle {t}: {} -> {}", ins.join(" "), outs.join(" "))?;
+            let wrong: Vec<String> = outputs
+                .iter()
+                .filter(|(_, want, got)| want != got)
+                .map(|(name, _, got)| format!("{name}={}", got.vector_char()))
+                .collect();
+            if !wrong.is_empty() {
+                write!(f, ", but the fuses give {}", wrong.join(" "))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    /// Proven equivalent by k-induction at this depth.
+    Equivalent(usize),
+    /// No mismatch within this many cycles of power-up, but no proof either.
+    Unknown(usize),
+    Mismatch(Counterexample),
+}
+
+struct Prover<'a> {
+    graph: &'a Graph,
+    pcf: &'a PcfFile,
+    model: ChipModel,
+    num_pins: u32,
+    inputs: Vec<(String, u32)>,
+    outputs: Vec<Output>,
+    /// Registered OLMCs in the netlist, with the chip OLMC on the same pin if it is
+    /// registered too, and the netlist register's power-up value.
+    regs: Vec<(NodeIdx, Option<usize>, bool)>,
+    /// Pin 11 is the 16V8 output enable, and is tied low unless the design uses it.
+    oe_pin: bool,
+    compare_regs: bool,
+}
+
+impl Prover<'_> {
+    /// Unroll `cycles` cycles, from power-up or from any state.
+    fn unroll(&self, s: &mut Solver, cycles: usize, init: bool) -> Result<Vec<Frame>, EquivError> {
+        let olmcs = &self.model.olmcs;
+        let mut chip_regs: Vec<Lit> = match init {
+            true => olmcs.iter().map(|o| s.constant(o.power_up)).collect(),
+            false => olmcs.iter().map(|_| s.new_var()).collect(),
+        };
+        let mut net_regs: HashMap<usize, Lit> = self
+            .regs
+            .iter()
+            .map(|(idx, _, power_up)| {
+                (idx.0, if init { s.constant(*power_up) } else { s.new_var() })
+            })
+            .collect();
+
+        let mut frames = Vec::new();
+        for _ in 0..cycles {
+            let pins: BTreeMap<u32, Lit> = (1..=self.num_pins).map(|p| (p, s.new_var())).collect();
+            let oe_n = if self.oe_pin {
+                pins[&11]
+            } else {
+                s.constant(false)
+            };
+            let mut chip = ChipFrame {
+                model: &self.model,
+                pins: &pins,
+                regs: &chip_regs,
+                oe_n,
+                drives: vec![None; olmcs.len()],
+                outs: vec![None; olmcs.len()],
+                visiting: vec![false; olmcs.len()],
+                driving: vec![false; olmcs.len()],
+            };
+            let mut net = NetFrame {
+                graph: self.graph,
+                pcf: self.pcf,
+                pins: &pins,
+                regs: &net_regs,
+                values: HashMap::new(),
+                visiting: HashSet::new(),
+            };
+
+            let mut outputs = Vec::new();
+            let mut ok = Vec::new();
+            for out in &self.outputs {
+                let (want_en, want) = net.olmc(s, out.olmc)?;
+                let i = self.model.olmc_at(out.pin).unwrap();
+                let (got_en, got) = chip.drive(s, i)?;
+                let en_differs = s.xor(want_en, got_en);
+                let value_differs = s.xor(want, got);
+                let both = s.and(&[want_en, value_differs]);
+                ok.push(!s.or(&[en_differs, both]));
+                outputs.push([want_en, want, got_en, got]);
+            }
+            let mut same_regs = Vec::new();
+            for (idx, chip_olmc, _) in &self.regs {
+                if let Some(i) = chip_olmc {
+                    // the pin shows the inverse of the chip's register.
+                    let differs = s.xor(net_regs[&idx.0], !chip_regs[*i]);
+                    same_regs.push(!differs);
+                }
+            }
+
+            let mut next_chip = Vec::new();
+            for (i, olmc) in olmcs.iter().enumerate() {
+                next_chip.push(if olmc.registered {
+                    chip.out(s, i)?
+                } else {
+                    chip_regs[i]
+                });
+            }
+            let mut next_net = HashMap::new();
+            for (idx, _, _) in &self.regs {
+                next_net.insert(idx.0, net.next(s, *idx)?);
+            }
+
+            frames.push(Frame {
+                pins,
+                outputs,
+                outputs_ok: s.and(&ok),
+                regs_ok: s.and(&same_regs),
+            });
+            chip_regs = next_chip;
+            net_regs = next_net;
+        }
+        Ok(frames)
+    }
+
+    fn level(s: &Solver, en: Lit, value: Lit) -> PinState {
+        match (s.value(en), s.value(value)) {
+            (Some(false), _) => PinState::HighZ,
+            (_, Some(true)) => PinState::High,
+            (_, Some(false)) => PinState::Low,
+            _ => PinState::Unknown,
+        }
+    }
+
+    /// Look for a mismatch within `cycles` cycles of power-up.
+    fn base(&self, cycles: usize) -> Result<Option<Counterexample>, EquivError> {
+        let mut s = Solver::new();
+        let frames = self.unroll(&mut s, cycles, true)?;
+        let bad: Vec<Lit> = frames.iter().map(|f| !f.outputs_ok).collect();
+        s.add_clause(&bad);
+        if !solve(&mut s)? {
+            return Ok(None);
+        }
+        let last = frames
+            .iter()
+            .position(|f| s.value(f.outputs_ok) == Some(false));
+        let frames = &frames[..=last.unwrap_or(frames.len() - 1)];
+        Ok(Some(Counterexample {
+            inputs: frames
+                .iter()
+                .map(|f| {
+                    self.inputs
+                        .iter()
+                        .map(|(name, pin)| (name.clone(), s.value(f.pins[pin]) == Some(true)))
+                        .collect()
+                })
+                .collect(),
+            outputs: frames
+                .iter()
+                .map(|f| {
+                    self.outputs
+                        .iter()
+                        .zip(&f.outputs)
+                        .map(|(out, [we, w, ge, g])| {
+                            let want = Self::level(&s, *we, *w);
+                            (out.name.clone(), want, Self::level(&s, *ge, *g))
+                        })
+                        .collect()
+                })
+                .collect(),
+        }))
+    }
+
+    /// Check whether the registers on matching pins agree for `cycles` cycles of power-up.
+    fn base_regs(&self, cycles: usize) -> Result<bool, EquivError> {
+        let mut s = Solver::new();
+        let frames = self.unroll(&mut s, cycles, true)?;
+        let bad: Vec<Lit> = frames.iter().map(|f| !f.regs_ok).collect();
+        s.add_clause(&bad);
+        Ok(!solve(&mut s)?)
+    }
+
+    /// Check that `k` good cycles from any state are always followed by another.
+    fn step(&self, k: usize) -> Result<bool, EquivError> {
+        let mut s = Solver::new();
+        let frames = self.unroll(&mut s, k + 1, false)?;
+        for f in &frames[..k] {
+            s.add_clause(&[f.outputs_ok]);
+            if self.compare_regs {
+                s.add_clause(&[f.regs_ok]);
+            }
+        }
+        let last = &frames[k];
+        let mut bad = vec![!last.outputs_ok];
+        if self.compare_regs {
+            bad.push(!last.regs_ok);
+        }
+        s.add_clause(&bad);
+        Ok(!solve(&mut s)?)
+    }
+}
+
+/// Prove a fuse map equivalent to a netlist, unrolling at most `depth` cycles.
+pub fn prove(
+    graph: &Graph,
+    pcf: &PcfFile,
+    fuses: &[bool],
+    depth: usize,
+) -> Result<Verdict, EquivError> {
+    let chip = chip_for_fuse_count(fuses.len()).ok_or(EquivError::UnknownFuseCount(fuses.len()))?;
+    let model = ChipModel::decode(chip, fuses)?;
+
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    for port in &graph.ports {
+        let pin = port
+            .lookup(pcf)
+            .ok_or_else(|| EquivError::NoPin(port.name.clone()))?;
+        if pin == 0 || pin as usize > chip.num_pins() {
+            return Err(EquivError::PinOutOfRange {
+                port: port.name.clone(),
+                pin,
+                chip: chip.name().to_string(),
+            });
+        }
+        let olmc = graph
+            .drivers(&port.net)
+            .into_iter()
+            .find(|c| matches!(graph.nodelist[c.node.0], Node::Olmc(_)))
+            .map(|c| c.node);
+        let olmc = match (&port.direction, olmc) {
+            // an inout port that no OLMC drives is only ever read.
+            (PortDirection::Input, _) | (PortDirection::Inout, None) => {
+                inputs.push((port.name.clone(), pin));
+                continue;
+            }
+            (_, olmc) => olmc.ok_or_else(|| EquivError::NotOlmc(port.name.clone()))?,
+        };
+        if model.olmc_at(pin).is_none() {
+            return Err(EquivError::NoOlmcPin {
+                port: port.name.clone(),
+                pin,
+            });
+        }
+        outputs.push(Output {
+            name: port.name.clone(),
+            pin,
+            olmc,
+        });
+    }
+
+    let regs = graph
+        .nodelist
+        .iter()
+        .enumerate()
+        .filter_map(|(i, n)| match n {
+            Node::Olmc(o) if o.parameters.registered => Some((NodeIdx(i), o)),
+            _ => None,
+        })
+        .map(|(idx, o)| {
+            let chip_olmc = outputs
+                .iter()
+                .find(|o| o.olmc == idx)
+                .and_then(|o| model.olmc_at(o.pin))
+                .filter(|i| model.olmcs[*i].registered);
+            // the pin shows the inverse of the chip's register.
+            let init = o
+                .connections
+                .get("Y")
+                .and_then(|y| y.first())
+                .and_then(|y| graph.inits.get(y));
+            let power_up = match (init, chip_olmc) {
+                (Some(init), _) => *init,
+                (None, Some(i)) => !model.olmcs[i].power_up,
+                (None, None) => power_up_level(chip, o.parameters.inverted),
+            };
+            (idx, chip_olmc, power_up)
+        })
+        .collect();
+
+    let mut prover = Prover {
+        graph,
+        pcf,
+        num_pins: chip.num_pins() as u32,
+        oe_pin: chip == Chip::GAL16V8 && inputs.iter().any(|(_, p)| *p == 11),
+        model,
+        inputs,
+        outputs,
+        regs,
+        compare_regs: true,
+    };
+    // a search that gives up only leaves the verdict unknown.
+    for k in 1..=depth.max(1) {
+        match prover.base(k) {
+            Ok(Some(cex)) => return Ok(Verdict::Mismatch(cex)),
+            Ok(None) => {}
+            Err(EquivError::GaveUp(_)) => return Ok(Verdict::Unknown(k - 1)),
+            Err(e) => return Err(e),
+        }
+        if prover.compare_regs {
+            match prover.base_regs(k) {
+                Ok(true) => {}
+                Ok(false) | Err(EquivError::GaveUp(_)) => prover.compare_regs = false,
+                Err(e) => return Err(e),
+            }
+        }
+        match prover.step(k) {
+            Ok(true) => return Ok(Verdict::Equivalent(k)),
+            Ok(false) | Err(EquivError::GaveUp(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(Verdict::Unknown(depth))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fitter::{auto_assign, fit};
+    use crate::jedec::{galette_jedec, parse};
+    use crate::pcf::parse_pcf;
+    use crate::test_util::{hand_netlist, input, olmc, ports, sop, testcase_pcf};
+    use galette::gal::Mode;
+    use galette::gal_builder::build;
+
+    /// `y = a & b` through a GAL_INPUT per input, optionally registered.
+    fn and_graph(registered: bool) -> Graph {
+        let mut g = Graph::default();
+        ports(
+            &mut g,
+            &[
+                ("a", 2, PortDirection::Input),
+                ("b", 3, PortDirection::Input),
+                ("y", 9, PortDirection::Output),
+            ],
+        );
+        g.nodelist.push(input(2, 4));
+        g.nodelist.push(input(3, 5));
+        g.nodelist.push(sop(&[4, 5], 6, "1010"));
+        g.nodelist.push(olmc(6, Net::LiteralOne, 9, registered));
+        g.generate_adjacency();
+        g
+    }
+
+    /// A 16V8 fuse map in registered mode, with pin 19 driven by `a & b` on pins 2 and 3.
+    fn and_fuses(registered: bool, xor: bool) -> Vec<bool> {
+        let mut fuses = vec![true; 2194];
+        fuses[2192] = false;
+        // OLMC 0 is pin 19, rows 0 to 7. Use the first product that drives the output.
+        let row = if registered { 0 } else { 1 };
+        // pin 2 is column 0 and pin 3 is column 4, true literals.
+        fuses[row * 32] = false;
+        fuses[row * 32 + 4] = false;
+        for r in 0..8 {
+            fuses[2128 + r] = r == row || (!registered && r == 0);
+        }
+        // in combinational mode, row 0 is the output enable: always true.
+        fuses[2048] = xor;
+        fuses[2120] = !registered;
+        fuses
+    }
+
+    #[test]
+    fn test_combinational() {
+        let g = and_graph(false);
+        let pcf = parse_pcf("set_io a 2\nset_io b 3\nset_io y 19");
+        assert_eq!(
+            prove(&g, &pcf, &and_fuses(false, true), 4).unwrap(),
+            Verdict::Equivalent(1)
+        );
+        // without the XOR fuse the output is inverted.
+        let Verdict::Mismatch(cex) = prove(&g, &pcf, &and_fuses(false, false), 4).unwrap() else {
+            panic!("expected a mismatch");
+        };
+        assert_eq!(cex.inputs.len(), 1);
+        let (_, want, got) = &cex.outputs[0][0];
+        assert_ne!(want, got);
+        assert!(cex.to_string().contains("but the fuses give y="), "{cex}");
+
+        // a pin the chip doesn't have is an error, not a panic.
+        let pcf = parse_pcf("set_io a 25\nset_io b 3\nset_io y 19");
+        assert!(matches!(
+            prove(&g, &pcf, &and_fuses(false, true), 4),
+            Err(EquivError::PinOutOfRange { pin: 25, .. })
+        ));
+    }
+
+    #[test]
+    fn test_registered() {
+        let g = and_graph(true);
+        let pcf = parse_pcf("set_io a 2\nset_io b 3\nset_io y 19");
+        assert_eq!(
+            prove(&g, &pcf, &and_fuses(true, true), 4).unwrap(),
+            Verdict::Equivalent(1)
+        );
+        // the wrong polarity shows up after the first clock, since both power up high.
+        let Verdict::Mismatch(cex) = prove(&g, &pcf, &and_fuses(true, false), 4).unwrap() else {
+            panic!("expected a mismatch");
+        };
+        assert_eq!(cex.inputs.len(), 2);
+        assert_eq!(cex.outputs[0][0].1, PinState::High);
+        assert_eq!(cex.outputs[0][0].2, PinState::High);
+
+        // the 16V8 cannot power up low.
+        let mut g = g;
+        g.inits.insert(Net::N(9), false);
+        let Verdict::Mismatch(cex) = prove(&g, &pcf, &and_fuses(true, true), 4).unwrap() else {
+            panic!("expected a mismatch");
+        };
+        assert_eq!(cex.inputs.len(), 1);
+        assert_eq!(cex.outputs[0][0].1, PinState::Low);
+    }
+
+    #[test]
+    fn test_decode_product() {
+        let mut fuses = vec![true; 8];
+        assert_eq!(decode_product(&fuses, 0, 4), Some(vec![]));
+        fuses[2] = false;
+        fuses[7] = false;
+        assert_eq!(
+            decode_product(&fuses, 0, 4),
+            Some(vec![(1, false), (3, true)])
+        );
+        fuses[3] = false;
+        assert_eq!(decode_product(&fuses, 0, 4), None);
+    }
+
+    /// The fuses galette builds for a fit, read back from its JEDEC file.
+    fn built_fuses(g: &Graph, pcf: &PcfFile, chip: Chip) -> Vec<bool> {
+        let fit = fit(g, pcf, chip).unwrap();
+        let mut gal = build(&fit.blueprint).unwrap();
+        if chip == Chip::GAL16V8 {
+            gal.set_mode(Mode::Registered);
+        }
+        parse(&galette_jedec(&gal)).unwrap().fuses
+    }
+
+    #[test]
+    fn test_up_counter_testcase() {
+        let counter = hand_netlist("up_counter_downto");
+        for chip in [Chip::GAL16V8, Chip::GAL22V10] {
+            // the other polarity flips which register value the feedback reads.
+            for inverted in [false, true] {
+                let mut g = counter.clone();
+                for node in &mut g.nodelist {
+                    if let Node::Olmc(o) = node {
+                        o.parameters.inverted = inverted;
+                    }
+                }
+                let pcf = match chip {
+                    Chip::GAL16V8 => testcase_pcf("up_counter"),
+                    _ => auto_assign(&g, chip).unwrap(),
+                };
+                let fuses = built_fuses(&g, &pcf, chip);
+                let verdict = prove(&g, &pcf, &fuses, 8).unwrap();
+                assert!(
+                    matches!(verdict, Verdict::Equivalent(_)),
+                    "{} inverted={inverted}: {verdict:?}",
+                    chip.name()
+                );
+            }
+        }
+    }
+}
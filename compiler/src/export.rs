@@ -0,0 +1,311 @@
+//! Render the netlist graph for debugging, as Graphviz DOT or JSON.
+//!
+//! Every net becomes an edge from each of its drivers to each of its sinks, labelled with the
+//! net name. Module ports are drawn as their own nodes. When a fit is given, OLMCs and inputs are
+//! annotated with the row and pin they were placed on.
+
+use crate::fitter::{Fit, Location};
+use crate::yosys_parser::{Graph, Net, NetConn, Node, NodeIdx, PortDirection};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+/// The cell type, as yosys names it.
+fn cell_type(node: &Node) -> &'static str {
+    match node {
+        Node::Input(_) => "GAL_INPUT",
+        Node::Sop(_) => "GAL_SOP",
+        Node::Olmc(_) => "GAL_OLMC",
+    }
+}
+
+/// An OLMC is buried when its output doesn't leave the chip.
+fn is_buried(graph: &Graph, node: &Node) -> bool {
+    match node {
+        Node::Olmc(o) => o
+            .connections
+            .get("Y")
+            .and_then(|y| y.first())
+            .is_none_or(|net| graph.find_port(net).is_none()),
+        _ => false,
+    }
+}
+
+/// Short description of the cell parameters: the SOP size, or the OLMC flags.
+fn cell_details(graph: &Graph, node: &Node) -> Vec<String> {
+    match node {
+        Node::Input(_) => Vec::new(),
+        Node::Sop(s) => vec![format!(
+            "{} products x {} inputs",
+            s.parameters.depth, s.parameters.width
+        )],
+        Node::Olmc(o) => {
+            let mut flags = Vec::new();
+            if o.parameters.registered {
+                flags.push("registered".to_string());
+            }
+            if o.parameters.inverted {
+                flags.push("inverted".to_string());
+            }
+            if is_buried(graph, node) {
+                flags.push("buried".to_string());
+            }
+            flags
+        }
+    }
+}
+
+/// Locations from a fit of `fitted`, moved over to the nodes of `graph` with the same name.
+fn locations(graph: &Graph, fit: Option<(&Fit, &Graph)>) -> BTreeMap<NodeIdx, Location> {
+    let Some((fit, fitted)) = fit else {
+        return BTreeMap::new();
+    };
+    let by_name: HashMap<&str, Location> = fit
+        .locations(fitted)
+        .into_iter()
+        .filter_map(|(idx, loc)| Some((fitted.get_node(&idx)?.name_ref()?, loc)))
+        .collect();
+    graph
+        .nodelist
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, node)| Some((NodeIdx(idx), *by_name.get(node.name_ref()?)?)))
+        .collect()
+}
+
+fn direction_name(dir: &PortDirection) -> &'static str {
+    match dir {
+        PortDirection::Input => "input",
+        PortDirection::Output => "output",
+        PortDirection::Inout => "inout",
+    }
+}
+
+/// All the `N` nets in the graph, in order.
+fn nets(graph: &Graph) -> Vec<&Net> {
+    let mut nets: Vec<&Net> = graph
+        .netindex
+        .keys()
+        .chain(graph.ports.iter().map(|p| &p.net))
+        .filter(|n| matches!(n, Net::N(_)))
+        .collect();
+    nets.sort();
+    nets.dedup();
+    nets
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Render the graph as a Graphviz digraph. The fit is given with the graph it was made from,
+/// which is usually an optimised copy of `graph`.
+pub fn to_dot(graph: &Graph, fit: Option<(&Fit, &Graph)>) -> String {
+    let locs = locations(graph, fit);
+    let mut out = String::from("digraph netlist {\n    rankdir=LR;\n");
+
+    for (idx, node) in graph.nodelist.iter().enumerate() {
+        let mut label = vec![cell_type(node).to_string(), node.name()];
+        label.extend(cell_details(graph, node));
+        if let Some(loc) = locs.get(&NodeIdx(idx)) {
+            label.push(match loc.row {
+                Some(row) => format!("row {row}, pin {}", loc.pin),
+                None => format!("pin {}", loc.pin),
+            });
+        }
+        let shape = match node {
+            Node::Input(_) => "invtriangle",
+            Node::Sop(_) => "box",
+            Node::Olmc(_) => "box3d",
+        };
+        let style = if is_buried(graph, node) { ", style=dashed" } else { "" };
+        let label: Vec<String> = label.iter().map(|l| escape(l)).collect();
+        writeln!(
+            out,
+            "    n{idx} [shape={shape}{style}, label=\"{}\"];",
+            label.join("\\n")
+        )
+        .unwrap();
+    }
+    for (i, port) in graph.ports.iter().enumerate() {
+        let pin = fit.and_then(|(f, _)| port.lookup(&f.pcf));
+        let label = match pin {
+            Some(pin) => format!("{} (pin {pin})", port.name),
+            None => port.name.clone(),
+        };
+        writeln!(out, "    p{i} [shape=plaintext, label=\"{}\"];", escape(&label)).unwrap();
+    }
+
+    for net in nets(graph) {
+        let name = escape(&graph.net_name(net));
+        let mut from: Vec<String> = graph
+            .drivers(net)
+            .iter()
+            .map(|c| format!("n{}", c.node.0))
+            .collect();
+        let mut to: Vec<String> = graph
+            .sinks(net)
+            .iter()
+            .map(|c| format!("n{}", c.node.0))
+            .collect();
+        for (i, port) in graph.ports.iter().enumerate() {
+            if &port.net != net {
+                continue;
+            }
+            if port.direction != PortDirection::Output {
+                from.push(format!("p{i}"));
+            }
+            if port.direction != PortDirection::Input {
+                to.push(format!("p{i}"));
+            }
+        }
+        for f in &from {
+            for t in to.iter().filter(|t| *t != f) {
+                writeln!(out, "    {f} -> {t} [label=\"{name}\"];").unwrap();
+            }
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Render the graph as JSON, with a list of nodes, ports and nets. The fit is passed as for
+/// `to_dot`.
+pub fn to_json(graph: &Graph, fit: Option<(&Fit, &Graph)>) -> Value {
+    let locs = locations(graph, fit);
+    let nodes: Vec<Value> = graph
+        .nodelist
+        .iter()
+        .enumerate()
+        .map(|(idx, node)| {
+            let mut v = json!({
+                "id": idx,
+                "type": cell_type(node),
+                "name": node.name(),
+            });
+            match node {
+                Node::Input(_) => {}
+                Node::Sop(s) => {
+                    v["width"] = json!(s.parameters.width);
+                    v["depth"] = json!(s.parameters.depth);
+                }
+                Node::Olmc(o) => {
+                    v["registered"] = json!(o.parameters.registered);
+                    v["inverted"] = json!(o.parameters.inverted);
+                    v["buried"] = json!(is_buried(graph, node));
+                }
+            }
+            if let Some(loc) = locs.get(&NodeIdx(idx)) {
+                v["row"] = json!(loc.row);
+                v["pin"] = json!(loc.pin);
+            }
+            v
+        })
+        .collect();
+    let ports: Vec<Value> = graph
+        .ports
+        .iter()
+        .map(|p| {
+            json!({
+                "name": p.name,
+                "direction": direction_name(&p.direction),
+                "net": graph.net_name(&p.net),
+                "pin": fit.and_then(|(f, _)| p.lookup(&f.pcf)),
+            })
+        })
+        .collect();
+    let nets: Vec<Value> = nets(graph)
+        .into_iter()
+        .map(|net| {
+            let conns = |list: Vec<&NetConn>| -> Vec<Value> {
+                list.iter()
+                    .map(|c| json!({"node": c.node.0, "port": c.port, "bit": c.bit}))
+                    .collect()
+            };
+            json!({
+                "name": graph.net_name(net),
+                "drivers": conns(graph.drivers(net)),
+                "sinks": conns(graph.sinks(net)),
+            })
+        })
+        .collect();
+    json!({ "nodes": nodes, "ports": ports, "nets": nets })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pcf::PcfFile;
+    use crate::test_util::testcase;
+    use anyhow::Result;
+    use galette::blueprint::Blueprint;
+    use galette::chips::Chip;
+
+    #[test]
+    fn test_dot() -> Result<()> {
+        let g = testcase("synth_olmc_test");
+        let dot = to_dot(&g, None);
+        assert!(dot.starts_with("digraph netlist {"));
+        assert!(dot.contains("GAL_SOP"));
+        assert!(dot.contains("[label=\"$iopadmap$AND\"]"));
+        assert_eq!(dot.matches("box3d").count(), g.get_olmc_idx().len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_annotated() -> Result<()> {
+        let g = testcase("synth_olmc_test");
+        let olmc = g.get_olmc_idx()[0];
+        let mut olmcmap = vec![None; 8];
+        olmcmap[3] = Some(olmc);
+        let fit = Fit {
+            chip: Chip::GAL16V8,
+            blueprint: Blueprint::new(Chip::GAL16V8),
+            olmcmap,
+            pcf: PcfFile::default(),
+        };
+        let v = to_json(&g, Some((&fit, &g)));
+        let node = &v["nodes"][olmc.0];
+        assert_eq!(node["type"], "GAL_OLMC");
+        assert_eq!(node["row"], 3);
+        assert_eq!(node["pin"], 15);
+        assert_eq!(v["ports"].as_array().unwrap().len(), g.ports.len());
+        let and = v["nets"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|n| n["name"] == "$iopadmap$AND")
+            .unwrap();
+        assert_eq!(and["sinks"].as_array().unwrap().len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_annotate_from_optimised() -> Result<()> {
+        let g = testcase("synth_olmc_test");
+        // the fitted copy lost a cell, so its node indices are shifted.
+        let mut fitted = g.clone();
+        fitted.nodelist.remove(0);
+        fitted.generate_adjacency();
+        let olmc = fitted.get_olmc_idx()[0];
+        let name = fitted.get_node(&olmc).unwrap().name();
+        let mut olmcmap = vec![None; 8];
+        olmcmap[2] = Some(olmc);
+        let fit = Fit {
+            chip: Chip::GAL16V8,
+            blueprint: Blueprint::new(Chip::GAL16V8),
+            olmcmap,
+            pcf: PcfFile::default(),
+        };
+        let v = to_json(&g, Some((&fit, &fitted)));
+        let node = v["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|n| n["name"] == name.as_str())
+            .unwrap();
+        assert_eq!(node["row"], 2);
+        assert_eq!(node["pin"], 14);
+        Ok(())
+    }
+}
@@ -1,8 +1,10 @@
+use std::collections::BTreeMap;
 use std::str::from_utf8;
 
 use crate::pcf::PcfFile;
+use crate::sop::{Cover, Literal};
 use crate::yosys_parser::{
-    GalSop, Graph, NamedPort, Net, Node, NodeIdx, PortDirection,
+    GalOLMC, GalSop, Graph, NamedPort, Net, Node, NodeIdx, PortDirection,
 };
 use galette::blueprint::{Blueprint, PinMode};
 use galette::chips::Chip;
@@ -22,6 +24,9 @@ pub enum MappingError {
     #[error("Could not find the SOP input")]
     MissingSOP,
 
+    #[error("SOP {0} has no A input")]
+    SopMissingInputs(String),
+
     #[error("Could not find a sop to fit SOP {name} of {sop_size}, wanted {wanted_size}")]
     SopTooBig {
         name: String,
@@ -29,6 +34,33 @@ pub enum MappingError {
         wanted_size: usize,
     },
 
+    #[error("Net {0} has no driver")]
+    Undriven(String),
+
+    #[error("Net {0} has more than one driver")]
+    MultipleDrivers(String),
+
+    #[error("No free pin left to assign port {0}")]
+    NoFreePin(String),
+
+    #[error("Could not partition the design across {0} chips")]
+    PartitionFailed(usize),
+
+    #[error("Register {name} is initialised to {init}, but cannot power up that way")]
+    InitValue { name: String, init: u8 },
+
+    #[error("The output enable of registered output {0} must be an active-low input on pin 11")]
+    RegisteredEnable(String),
+
+    #[error("Registered outputs {0} and {1} have different output enables, but the GAL16V8 has one, on pin 11")]
+    MixedEnables(String, String),
+
+    #[error("Inout port {0} is always driven, so it can never be read from outside")]
+    InoutAlwaysEnabled(String),
+
+    #[error("Inout port {0} is read back, but its registered output feeds back the register, not the pin")]
+    InoutRegistered(String),
+
     #[error("Unknown error")]
     Unknown,
 
@@ -46,7 +78,7 @@ fn get_sop_for_olmc(graph: &Graph, olmc_idx: &NodeIdx, port: &str) -> Result<Gal
     let sops_on_net: Vec<_> = input
         .iter()
         .filter_map(|i| {
-            let driver_cell = i.get_other(olmc_idx)?;
+            let driver_cell = i.get_other(olmc_idx, port)?;
             if driver_cell.1 != "Y" {
                 return None;
             };
@@ -66,24 +98,30 @@ fn get_sop_for_olmc(graph: &Graph, olmc_idx: &NodeIdx, port: &str) -> Result<Gal
 
 fn map_remaining_olmc(
     graph: &Graph,
+    chip: Chip,
     olmc: NodeIdx,
-    unused: &Vec<(usize, usize)>,
+    unused: &[usize],
 ) -> Result<(usize, usize), MappingError> {
     // (index, size)
     let mut chosen_row: Option<(usize, usize)> = None;
     let sop = get_sop_for_olmc(graph, &olmc, "A")?;
     let sopsize: usize = sop.parameters.depth as usize;
+    let registered = matches!(graph.get_node(&olmc), Some(Node::Olmc(o)) if o.parameters.registered);
+    let unused: Vec<(usize, usize)> = unused
+        .iter()
+        .map(|row| (*row, row_capacity(chip, *row, registered)))
+        .collect();
 
-    for (olmc_idx, size) in unused {
+    for (olmc_idx, size) in &unused {
         match chosen_row {
             None => {
-                if size > &sopsize {
+                if size >= &sopsize {
                     chosen_row = Some((*olmc_idx, *size));
                 }
             }
             Some(r) => {
-                // we do the comparison (size > SOP Size)
-                if size < &r.1 && size > &sopsize {
+                // we do the comparison (size >= SOP Size)
+                if size < &r.1 && size >= &sopsize {
                     chosen_row = Some((*olmc_idx, *size));
                 }
             }
@@ -110,7 +148,8 @@ fn map_remaining_olmc(
     }
 }
 
-fn chip_to_olmc_offset(chip: &Chip) -> usize {
+/// Pin number of OLMC row 0.
+pub fn chip_to_olmc_offset(chip: &Chip) -> usize {
     match chip {
         Chip::GAL16V8 => 12,
         Chip::GAL22V10 => 14,
@@ -118,67 +157,88 @@ fn chip_to_olmc_offset(chip: &Chip) -> usize {
     }
 }
 
+/// The level a registered OLMC's `Y` output has at power-up. The registers clear to zero,
+/// which the 16V8 always reads out through an inverter. The 22V10's XOR sits after the
+/// register, so only its inverted outputs power up high.
+pub fn power_up_level(chip: Chip, inverted: bool) -> bool {
+    match chip {
+        Chip::GAL22V10 => inverted,
+        _ => true,
+    }
+}
+
+/// Returns true if the array reads an OLMC's output back inverted. The 22V10 feeds a registered
+/// output back from the register, ahead of the XOR that sets its polarity, so the feedback is
+/// the inverse of an output that isn't inverted. Everything else reads back what the pin shows.
+pub fn feedback_inverted(chip: Chip, registered: bool, inverted: bool) -> bool {
+    chip == Chip::GAL22V10 && registered && !inverted
+}
+
+/// Number of product terms a row offers to the SOP on the OLMC's `A` input.
+/// Combinational outputs lose one row to the output enable term, as do all 22V10 outputs.
+pub fn row_capacity(chip: Chip, row: usize, registered: bool) -> usize {
+    match chip {
+        Chip::GAL16V8 if registered => chip.num_rows_for_olmc(row),
+        _ => chip.num_rows_for_olmc(row) - 1,
+    }
+}
+
+/// Find the pin the array reads a net from, and whether it reads it inverted.
 fn find_hwpin_for_net(
     graph: &Graph,
     pcf: &PcfFile,
     olmcmap: &[Option<NodeIdx>],
     chip: &Chip,
     net: &Net,
-) -> Result<u32, MappingError> {
-    // this does a double lookup. first it finds the Input on the net,
-    // then it finds the port on the input of the GAL_INPUT.
-    // find the input on the net.
-    let inputs: Vec<&Node> = graph
-        .find_nodes_on_net(net)
-        .iter()
-        .filter_map(|n| {
-            let node = graph.get_node(n)?;
-            match node {
-                Node::Input(_) => Some(node),
-                Node::Olmc(_) => Some(node),
-                _ => None,
-            }
-        })
-        .collect();
-
-    // now we have an array of inputs, this should be one driver element.
-    if inputs.len() != 1 {
-        return Err(MappingError::Unknown);
+) -> Result<(u32, bool), MappingError> {
+    // an inout pin is read back from the pin itself, whether or not an OLMC drives it.
+    if let Some(p) = graph
+        .find_port(net)
+        .filter(|p| p.direction == PortDirection::Inout)
+    {
+        let pin = p.lookup(pcf).ok_or(MappingError::MissingConstraint(p.clone()))?;
+        return Ok((pin, false));
     }
 
-    let conns = inputs[0].get_connections();
+    // this does a double lookup. first it finds the driver of the net,
+    // then for a GAL_INPUT it finds the port on its input.
+    let drivers = graph.drivers(net);
+    let driver = match drivers[..] {
+        [d] => d,
+        [] => return Err(MappingError::Undriven(graph.net_name(net))),
+        _ => return Err(MappingError::MultipleDrivers(graph.net_name(net))),
+    };
+    let node = graph.get_node(&driver.node).ok_or(MappingError::Unknown)?;
 
-    match inputs[0] {
-        Node::Input(_) => {
-            let port_nets = conns.get("A").ok_or(MappingError::Unknown)?;
+    match node {
+        Node::Input(i) => {
+            let port_nets = i.connections.get("A").ok_or(MappingError::Unknown)?;
             assert_eq!(port_nets.len(), 1, "should only be one input to GAL_INPUT");
             let pnet = &port_nets[0];
 
             if let Some(p) = graph.find_port(pnet) {
                 debug!("Found a port after traversing inputs, {:?}", p);
                 // look up the pin.
-                p.lookup(pcf)
-                    .ok_or(MappingError::MissingConstraint(p.clone()))
+                let pin = p
+                    .lookup(pcf)
+                    .ok_or(MappingError::MissingConstraint(p.clone()))?;
+                Ok((pin, false))
             } else {
                 Err(MappingError::Unknown)
             }
         }
-        Node::Olmc(_) => {
+        Node::Olmc(o) => {
             // find the row that this olmc is in.
             debug!("an olmc is driving this net, looking up what row it is");
-            let olmc_idx = graph
-                .nodelist
-                .iter()
-                .position(|node| node == inputs[0])
-                .unwrap();
             let olmc_row = olmcmap
                 .iter()
-                .position(|r| r == &Some(NodeIdx(olmc_idx)))
-                .unwrap();
+                .position(|r| r == &Some(driver.node))
+                .ok_or(MappingError::Unknown)?;
             // we have the row.
-            let pin = olmc_row + chip_to_olmc_offset(chip); // TODO: fix!
+            let pin = olmc_row + chip_to_olmc_offset(chip);
             debug!("OLMC discovered on {pin}");
-            Ok(pin as u32)
+            let inverted = feedback_inverted(*chip, o.parameters.registered, o.parameters.inverted);
+            Ok((pin as u32, inverted))
         }
         _ => Err(MappingError::Unknown),
     }
@@ -190,7 +250,7 @@ fn make_term_from_sop(
     olmcmap: &[Option<NodeIdx>],
     chip: &Chip,
     sop: GalSop,
-) -> Term {
+) -> Result<Term, MappingError> {
     let table = sop.parameters.table.as_bytes();
 
     let n_products = sop.parameters.depth;
@@ -198,44 +258,82 @@ fn make_term_from_sop(
     let chunksize = product_size * 2; // 00 for dontcare, 01 for negation, 10 for positive i think
     debug!("Making Term from SOP {:?}", sop);
 
-    let mut input_nets = sop.connections.get("A").unwrap().clone();
+    let mut input_nets = sop
+        .connections
+        .get("A")
+        .ok_or_else(|| MappingError::SopMissingInputs(sop.name.clone().unwrap_or_default()))?
+        .clone();
     input_nets.reverse(); // the order is backwards from how we read it in the alg.
 
-    let terms: Vec<Vec<Pin>> = table
-        .chunks(chunksize as usize)
-        .map(|chunk| {
-            // chunk is now a block of terms.
-            let terms: Vec<&str> = chunk.chunks(2).map(|c| from_utf8(c).unwrap()).collect();
-            // create our term
-            let pins: Vec<Pin> = terms
-                .iter()
-                .enumerate()
-                .filter_map(|(idx, product)| {
-                    let net_for_pin = input_nets.get(idx).unwrap();
+    let mut terms: Vec<Vec<Pin>> = Vec::new();
+    'products: for chunk in table.chunks(chunksize as usize) {
+        // chunk is now a block of terms.
+        let literals = chunk.chunks(2).map(|c| from_utf8(c).unwrap());
+        // create our term, folding away the constant inputs.
+        let mut pins = Vec::new();
+        for (net_for_pin, literal) in input_nets.iter().zip(literals) {
+            match fold_literal(net_for_pin, literal) {
+                Folded::Input(neg) => {
                     // now use the helper to find the true hardware pin
-                    let hwpin: usize = find_hwpin_for_net(graph, pcf, olmcmap, &chip, net_for_pin)
-                        .unwrap() as usize;
-                    // we now have our hardware pin number!
-                    match *product {
-                        "01" => Some(Pin {
-                            pin: hwpin,
-                            neg: true,
-                        }),
-                        "10" => Some(Pin {
-                            pin: hwpin,
-                            neg: false,
-                        }),
-                        _ => None,
-                    }
-                })
-                .collect();
-            pins
-        })
-        .collect();
-    assert_eq!(n_products as usize, terms.len());
-    Term {
+                    let (hwpin, inverted) =
+                        find_hwpin_for_net(graph, pcf, olmcmap, chip, net_for_pin)?;
+                    pins.push(Pin {
+                        pin: hwpin as usize,
+                        neg: neg != inverted,
+                    });
+                }
+                Folded::True => {}
+                // this product can never be true, so leave it out entirely.
+                Folded::False => continue 'products,
+            }
+        }
+        terms.push(pins);
+    }
+    if terms.len() != n_products as usize {
+        debug!(
+            "Folded constants: {} of {} products remain",
+            terms.len(),
+            n_products
+        );
+    }
+    Ok(Term {
         line_num: 0,
         pins: terms,
+    })
+}
+
+/// A single literal of a SOP product, after constant folding.
+#[derive(Debug, PartialEq)]
+enum Folded {
+    /// The literal reads a real net, possibly negated.
+    Input(bool),
+    /// The literal is always true, and can be dropped from the product.
+    True,
+    /// The literal is always false, so the whole product is.
+    False,
+}
+
+/// Fold a TABLE entry against the net it reads. Don't-cares are always true, whatever the net,
+/// and `x` nets are taken to be 0.
+fn fold_literal(net: &Net, literal: &str) -> Folded {
+    let positive = match literal {
+        "10" => true,
+        "01" => false,
+        _ => return Folded::True,
+    };
+    let value = match net {
+        Net::N(_) => return Folded::Input(!positive),
+        Net::LiteralOne => true,
+        Net::LiteralZero => false,
+        Net::NotConnected => {
+            warn!("SOP reads an unconnected net, treating it as 0");
+            false
+        }
+    };
+    if value == positive {
+        Folded::True
+    } else {
+        Folded::False
     }
 }
 
@@ -251,19 +349,363 @@ fn valid_inputs(chip: Chip) -> Vec<u32> {
     }
 }
 
+/// A fitted design: the galette blueprint plus the row assignment used to build it.
+pub struct Fit {
+    pub chip: Chip,
+    pub blueprint: Blueprint,
+    /// OLMC row to graph node. Rows claimed by input ports hold `NodeIdx(usize::MAX)`.
+    pub olmcmap: Vec<Option<NodeIdx>>,
+    /// The pin assignment the fit was made with.
+    pub pcf: PcfFile,
+}
+
+/// Where a node ended up on the chip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    /// OLMC row, or `None` for inputs on dedicated input pins.
+    pub row: Option<usize>,
+    pub pin: u32,
+}
+
+/// Resource usage of a fitted design.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Utilisation {
+    pub olmcs_used: usize,
+    pub olmcs_total: usize,
+    pub terms_used: usize,
+    pub terms_total: usize,
+}
+
+impl Fit {
+    /// The signal on each used pin: the port bound to it, or for a buried OLMC the name of
+    /// the net it drives.
+    pub fn pin_signals(&self, graph: &Graph) -> BTreeMap<u32, String> {
+        let mut signals = BTreeMap::new();
+        for port in &graph.ports {
+            if let Some(pin) = port.lookup(&self.pcf) {
+                signals.insert(pin, port.name.clone());
+            }
+        }
+        let offset = chip_to_olmc_offset(&self.chip);
+        for (row, node) in self.olmcmap.iter().enumerate() {
+            let pin = (row + offset) as u32;
+            let Some(Node::Olmc(o)) = node.and_then(|n| graph.get_node(&n)) else {
+                continue;
+            };
+            if let Some(y) = o.connections.get("Y").and_then(|y| y.first()) {
+                signals.entry(pin).or_insert_with(|| graph.net_name(y));
+            }
+        }
+        signals
+    }
+
+    /// The row and pin of every OLMC, and of every input that is bound to a port.
+    pub fn locations(&self, graph: &Graph) -> BTreeMap<NodeIdx, Location> {
+        let offset = chip_to_olmc_offset(&self.chip);
+        let mut locs = BTreeMap::new();
+        for (row, node) in self.olmcmap.iter().enumerate() {
+            if let Some(node) = node.filter(|n| n.0 != usize::MAX) {
+                let pin = (row + offset) as u32;
+                locs.insert(node, Location { row: Some(row), pin });
+            }
+        }
+        for (idx, node) in graph.nodelist.iter().enumerate() {
+            let Node::Input(inp) = node else {
+                continue;
+            };
+            let pin = inp
+                .connections
+                .get("A")
+                .and_then(|a| a.first())
+                .and_then(|net| graph.find_port(net))
+                .and_then(|port| port.lookup(&self.pcf));
+            if let Some(pin) = pin {
+                let row = self.chip.pin_to_olmc(pin as usize);
+                locs.insert(NodeIdx(idx), Location { row, pin });
+            }
+        }
+        locs
+    }
+
+    /// Count the OLMCs and product terms consumed by this fit.
+    pub fn utilisation(&self, graph: &Graph) -> Utilisation {
+        let mut olmcs_used = 0;
+        let mut terms_used = 0;
+        for node in self.olmcmap.iter().flatten() {
+            if node.0 == usize::MAX {
+                continue;
+            }
+            olmcs_used += 1;
+            for port in ["A", "E"] {
+                if let Ok(sop) = get_sop_for_olmc(graph, node, port) {
+                    terms_used += sop.parameters.depth as usize;
+                }
+            }
+        }
+        Utilisation {
+            olmcs_used,
+            olmcs_total: self.chip.num_olmcs(),
+            terms_used,
+            terms_total: (0..self.chip.num_olmcs())
+                .map(|i| self.chip.num_rows_for_olmc(i))
+                .sum(),
+        }
+    }
+}
+
+/// Input pins that aren't shared with an OLMC, and so are free for logic inputs.
+pub fn dedicated_inputs(chip: Chip, clocked: bool) -> Vec<u32> {
+    let mut pins: Vec<u32> = valid_inputs(chip)
+        .into_iter()
+        .filter(|p| chip.pin_to_olmc(*p as usize).is_none())
+        .collect();
+    if matches!(chip, Chip::GAL16V8) || clocked {
+        // pin 1 is the clock (and pin 11 the output enable in GAL16V8 registered mode).
+        pins.retain(|p| *p != 1 && !(matches!(chip, Chip::GAL16V8) && *p == 11));
+    }
+    pins
+}
+
+/// Find the input ports that feed OLMC clocks.
+fn clock_ports(graph: &Graph) -> Vec<&NamedPort> {
+    let clock_nets: Vec<&Net> = graph
+        .get_olmc()
+        .into_iter()
+        .filter_map(|node| match node {
+            Node::Olmc(o) => o.connections.get("C"),
+            _ => None,
+        })
+        .flatten()
+        .filter(|net| matches!(net, Net::N(_)))
+        .collect();
+    graph
+        .ports
+        .iter()
+        .filter(|port| port.direction == PortDirection::Input)
+        .filter(|port| {
+            // the port drives a GAL_INPUT, whose output is the clock net.
+            graph.find_nodes_on_net(&port.net).iter().any(|idx| {
+                let Some(Node::Input(i)) = graph.get_node(idx) else {
+                    return false;
+                };
+                i.connections
+                    .get("Y")
+                    .is_some_and(|y| y.iter().any(|n| clock_nets.contains(&n)))
+            })
+        })
+        .collect()
+}
+
+/// The input port that drives an OLMC's output enable through a single inverted literal,
+/// as the GAL16V8's pin 11 output enable does.
+fn active_low_enable_port<'a>(graph: &'a Graph, olmc: &NodeIdx) -> Option<&'a NamedPort> {
+    let sop = get_sop_for_olmc(graph, olmc, "E").ok()?;
+    let cover = Cover::from_params(&sop.parameters);
+    let [cube] = &cover.cubes[..] else {
+        return None;
+    };
+    let mut literals = cube
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| **l != Literal::DontCare);
+    let (Some((input, Literal::Zero)), None) = (literals.next(), literals.next()) else {
+        return None;
+    };
+    let net = sop.connections.get("A")?.get(input)?;
+    graph.drivers(net).into_iter().find_map(|c| match graph.get_node(&c.node) {
+        Some(Node::Input(i)) => graph.find_port(i.connections.get("A")?.first()?),
+        _ => None,
+    })
+}
+
+/// Check the output enables of the GAL16V8's registered outputs. The chip has one, the
+/// active-low input on pin 11, so the registered outputs must either all use it or all be
+/// always enabled. Returns the port on pin 11 if they use it.
+fn registered_enable<'a>(
+    graph: &'a Graph,
+    pcf: &PcfFile,
+) -> Result<Option<&'a NamedPort>, MappingError> {
+    let mut first: Option<(String, Option<&NamedPort>)> = None;
+    for idx in graph.get_olmc_idx() {
+        let Some(Node::Olmc(o)) = graph.get_node(&idx) else {
+            continue;
+        };
+        if !o.parameters.registered {
+            continue;
+        }
+        let name = o
+            .connections
+            .get("Y")
+            .and_then(|y| y.first())
+            .map_or_else(|| o.name.clone().unwrap_or_default(), |y| graph.net_name(y));
+        let enable = match o.connections.get("E").and_then(|e| e.first()) {
+            None | Some(Net::LiteralOne) => None,
+            Some(_) => match active_low_enable_port(graph, &idx) {
+                Some(port) if port.lookup(pcf).is_none_or(|pin| pin == 11) => Some(port),
+                _ => return Err(MappingError::RegisteredEnable(name)),
+            },
+        };
+        match &first {
+            None => first = Some((name, enable)),
+            Some((other, e)) if *e != enable => {
+                return Err(MappingError::MixedEnables(other.clone(), name))
+            }
+            _ => {}
+        }
+    }
+    Ok(first.and_then(|(_, enable)| enable))
+}
+
+/// The OLMC driving a net, if any.
+fn olmc_driving<'a>(graph: &'a Graph, net: &Net) -> Option<(NodeIdx, &'a GalOLMC)> {
+    graph
+        .drivers(net)
+        .into_iter()
+        .find_map(|c| match graph.get_node(&c.node) {
+            Some(Node::Olmc(o)) => Some((c.node, o)),
+            _ => None,
+        })
+}
+
+/// Returns true if an OLMC's output enable can never be off.
+fn always_enabled(graph: &Graph, idx: &NodeIdx, olmc: &GalOLMC) -> bool {
+    match olmc.connections.get("E").and_then(|e| e.first()) {
+        None | Some(Net::LiteralOne) => true,
+        Some(Net::N(_)) => get_sop_for_olmc(graph, idx, "E").is_ok_and(|sop| {
+            let inputs = &sop.connections["A"];
+            // a product whose literals are all don't-cares or constants that hold.
+            Cover::from_params(&sop.parameters).cubes.iter().any(|cube| {
+                cube.iter().zip(inputs).all(|(l, net)| match l {
+                    Literal::DontCare => true,
+                    Literal::One => *net == Net::LiteralOne,
+                    Literal::Zero => matches!(net, Net::LiteralZero | Net::NotConnected),
+                })
+            })
+        }),
+        _ => false,
+    }
+}
+
+/// Check every inout port can be read back through its pin. The OLMC driving it, if there is
+/// one, has to let go of the pin some of the time, and mustn't be registered if anything
+/// reads the pin, since registered OLMCs feed back the register instead.
+fn check_inouts(graph: &Graph) -> Result<(), MappingError> {
+    for port in &graph.ports {
+        if port.direction != PortDirection::Inout {
+            continue;
+        }
+        let Some((idx, olmc)) = olmc_driving(graph, &port.net) else {
+            continue;
+        };
+        if always_enabled(graph, &idx, olmc) {
+            return Err(MappingError::InoutAlwaysEnabled(port.name.clone()));
+        }
+        if olmc.parameters.registered && !graph.sinks(&port.net).is_empty() {
+            return Err(MappingError::InoutRegistered(port.name.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// Create pin constraints for a graph without a PCF file.
+///
+/// Clocks go on pin 1, outputs are placed largest SOP first onto the largest rows, and inputs
+/// use the dedicated input pins before spilling over onto unused OLMC pins. On the GAL16V8
+/// the output enable of the registered outputs goes on pin 11.
+pub fn auto_assign(graph: &Graph, chip: Chip) -> Result<PcfFile, MappingError> {
+    let mut pcf = PcfFile::default();
+    if chip == Chip::GAL16V8 {
+        if let Some(oe) = registered_enable(graph, &pcf)? {
+            pcf.insert(&oe.name, 11);
+        }
+    }
+    let clocks = clock_ports(graph);
+    let mut free_inputs = dedicated_inputs(chip, !clocks.is_empty());
+    if let Some(clk) = clocks.get(1) {
+        // there is only one clock pin.
+        return Err(MappingError::NoFreePin(clk.name.clone()));
+    }
+    if let Some(clk) = clocks.first() {
+        pcf.insert(&clk.name, 1);
+    }
+
+    // rows sorted from largest to smallest, so big SOPs land on big rows.
+    let mut free_rows: Vec<usize> = (0..chip.num_olmcs()).collect();
+    free_rows.sort_by_key(|r| std::cmp::Reverse(row_capacity(chip, *r, false)));
+
+    let mut outputs: Vec<(&NamedPort, usize)> = graph
+        .ports
+        .iter()
+        .filter(|port| port.direction != PortDirection::Input)
+        .map(|port| {
+            let depth = graph
+                .get_olmc_idx()
+                .into_iter()
+                .find(|idx| {
+                    matches!(graph.get_node(idx), Some(Node::Olmc(o))
+                        if o.connections.get("Y").is_some_and(|y| y.contains(&port.net)))
+                })
+                .and_then(|idx| get_sop_for_olmc(graph, &idx, "A").ok())
+                .map_or(0, |sop| sop.parameters.depth as usize);
+            (port, depth)
+        })
+        .collect();
+    outputs.sort_by_key(|(_, depth)| std::cmp::Reverse(*depth));
+    for (port, _) in outputs {
+        if free_rows.is_empty() {
+            return Err(MappingError::NoFreePin(port.name.clone()));
+        }
+        let row = free_rows.remove(0);
+        pcf.insert(&port.name, (row + chip_to_olmc_offset(&chip)) as u32);
+    }
+
+    for port in &graph.ports {
+        if port.direction != PortDirection::Input
+            || clocks.contains(&port)
+            || pcf.pin(&port.name).is_some()
+        {
+            continue;
+        }
+        let pin = if free_inputs.is_empty() {
+            // take the smallest row left over, leaving the big ones for buried OLMCs.
+            let row = free_rows
+                .pop()
+                .ok_or(MappingError::NoFreePin(port.name.clone()))?;
+            (row + chip_to_olmc_offset(&chip)) as u32
+        } else {
+            free_inputs.remove(0)
+        };
+        pcf.insert(&port.name, pin);
+    }
+    Ok(pcf)
+}
+
 pub fn graph_convert(graph: &Graph, pcf: &PcfFile, chip: Chip) -> Result<Blueprint, MappingError> {
+    fit(graph, pcf, chip).map(|f| f.blueprint)
+}
+
+/// Map the graph onto the chip, keeping the row assignment alongside the blueprint.
+pub fn fit(graph: &Graph, pcf: &PcfFile, chip: Chip) -> Result<Fit, MappingError> {
     let mut bp = Blueprint::new(chip);
 
     let valid_inp = valid_inputs(chip);
     let mut olmcmap: Vec<Option<NodeIdx>> = vec![None; chip.num_olmcs()];
 
+    check_inouts(graph)?;
+
     for port in &graph.ports {
         let pin = port
             .lookup(&pcf)
             .ok_or(MappingError::MissingConstraint(port.clone()))?;
         if valid_inp.contains(&pin) {
             if let Some(olmcrow) = chip.pin_to_olmc(pin as usize) {
-                if port.direction == PortDirection::Input {
+                // an inout port with no OLMC driving it is only ever read, like an input.
+                let read_only = match port.direction {
+                    PortDirection::Input => true,
+                    PortDirection::Inout => olmc_driving(graph, &port.net).is_none(),
+                    PortDirection::Output => false,
+                };
+                if read_only {
                     olmcmap[olmcrow] = Some(NodeIdx(usize::MAX));
                 } // otherwise we do not care at this point!
             }
@@ -288,8 +730,10 @@ pub fn graph_convert(graph: &Graph, pcf: &PcfFile, chip: Chip) -> Result<Bluepri
         // find all the
 
         let n: &Net;
+        let registered;
         if let Some(Node::Olmc(olmc)) = graph.get_node(&o) {
             n = &olmc.connections.get("Y").ok_or(MappingError::Unknown)?[0];
+            registered = olmc.parameters.registered;
         } else {
             warn!("Could not find output net! Silently skipping");
             continue;
@@ -308,11 +752,9 @@ pub fn graph_convert(graph: &Graph, pcf: &PcfFile, chip: Chip) -> Result<Bluepri
                 let olmc_row = chip
                     .pin_to_olmc(pin as usize)
                     .ok_or(MappingError::Unknown)?;
-                // TODO: check size of row vs size of SOP
-                // FIXME: -0 to size if registered, if comb, size - 1
                 let sop = get_sop_for_olmc(graph, &o, "A")?;
                 let sopsize: usize = sop.parameters.depth as usize;
-                let rowsize = chip.num_rows_for_olmc(olmc_row);
+                let rowsize = row_capacity(chip, olmc_row, registered);
                 if sopsize > rowsize {
                     return Err(MappingError::SopTooBig {
                         name: sop.name.unwrap(),
@@ -341,25 +783,30 @@ pub fn graph_convert(graph: &Graph, pcf: &PcfFile, chip: Chip) -> Result<Bluepri
 
     // to map the deferred ones, we need to find the smallest SOP that is still large enough for
     // it.
-    // Vec<(olmc_index,size)>
     let mut unused_rows = olmcmap
         .iter()
         .enumerate() // get the index
         .filter_map(|(i, x)| if x.is_none() { Some(i) } else { None }) // find the ones that are
-        .map(|i| (i, chip.num_rows_for_olmc(i))) // get the size of the row
-        .collect();
+        .collect::<Vec<usize>>();
 
     debug!("Unused rows={:?}", unused_rows);
     // find the smallest row that fits.
     info!("Starting deferred mapping process");
     for olmc in deferrals {
-        let row = map_remaining_olmc(graph, olmc, &unused_rows)?;
+        let row = map_remaining_olmc(graph, chip, olmc, &unused_rows)?;
         debug!("Found a mapping for {olmc} in row {} size {}", row.0, row.1);
         // insert into the mapping
         olmcmap[row.0] = Some(olmc);
         // remove this row from the available rows
         // i.e only keep those that are not equal to this row.
-        unused_rows.retain(|r| r != &row);
+        unused_rows.retain(|r| *r != row.0);
+    }
+
+    if chip == Chip::GAL16V8 {
+        // registered outputs share the pin 11 output enable, so they don't get their own.
+        if let Some(oe) = registered_enable(graph, pcf)? {
+            info!("Registered outputs are enabled by {} on pin 11", oe.name);
+        }
     }
 
     // at this point, we have mapped every OLMC.
@@ -367,11 +814,12 @@ pub fn graph_convert(graph: &Graph, pcf: &PcfFile, chip: Chip) -> Result<Bluepri
     info!("Deferred mapping complete, starting SOP mapping");
     for (idx, olmc) in olmcmap.iter().enumerate() {
         match olmc {
-            Some(node) => {
+            // rows claimed by input ports are left unprogrammed.
+            Some(node) if node.0 != usize::MAX => {
                 debug!("Mapping node {node} at row {idx}");
                 let sop = get_sop_for_olmc(graph, node, "A")?;
                 debug!("Got SOP {:?} attached to node", sop);
-                let term = make_term_from_sop(graph, pcf, &olmcmap, &chip, sop);
+                let term = make_term_from_sop(graph, pcf, &olmcmap, &chip, sop)?;
                 debug!("Got term {:?}", term);
                 let gal_olmc_node = graph.get_node(node).unwrap();
                 if let Node::Olmc(o) = gal_olmc_node {
@@ -389,7 +837,7 @@ pub fn graph_convert(graph: &Graph, pcf: &PcfFile, chip: Chip) -> Result<Bluepri
                             debug!("Sop found, {:?}", tri_sop);
                             assert_eq!(tri_sop.parameters.depth, 1);
                             let tri_term =
-                                make_term_from_sop(graph, pcf, &olmcmap, &chip, tri_sop);
+                                make_term_from_sop(graph, pcf, &olmcmap, &chip, tri_sop)?;
                             debug!("Term for tristate SOP made = {:?}", tri_term);
                             tri_term
                         }
@@ -429,21 +877,195 @@ pub fn graph_convert(graph: &Graph, pcf: &PcfFile, chip: Chip) -> Result<Bluepri
                     panic!("screaming");
                 }
             }
-            None => {}
+            _ => {}
         }
     }
 
-    Ok(bp)
+    Ok(Fit {
+        chip,
+        blueprint: bp,
+        olmcmap,
+        pcf: pcf.clone(),
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::pcf::parse_pcf;
+    use crate::test_util::{input, olmc, ports, sop, testcase};
+    use crate::yosys_parser::GalSopParameters;
     use anyhow::Result;
+    use std::collections::HashMap;
 
     #[test]
     fn test_sop_to_term() -> Result<()> {
-        let pct = "set_io pinName 1";
+        // "1 | 0 | x" with only constant inputs, so no pins are looked up.
+        let sop = GalSop {
+            name: None,
+            connections: HashMap::from([(
+                "A".to_string(),
+                vec![Net::LiteralZero, Net::NotConnected, Net::LiteralOne],
+            )]),
+            parameters: GalSopParameters {
+                depth: 3,
+                width: 3,
+                table: "100000".to_string() + "000010" + "001000",
+            },
+        };
+        let term = make_term_from_sop(
+            &Graph::default(),
+            &PcfFile::default(),
+            &[],
+            &Chip::GAL16V8,
+            sop,
+        )?;
+        // the first product is just "1", the other two can never be true.
+        assert_eq!(term.pins, vec![Vec::<Pin>::new()]);
+
+        // a net that isn't on any pin is an error, not a panic.
+        let sop = GalSop {
+            name: None,
+            connections: HashMap::from([("A".to_string(), vec![Net::N(5)])]),
+            parameters: GalSopParameters {
+                depth: 1,
+                width: 1,
+                table: "10".to_string(),
+            },
+        };
+        let pcf = PcfFile::default();
+        let res = make_term_from_sop(&Graph::default(), &pcf, &[], &Chip::GAL16V8, sop);
+        assert!(res.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fold_literal() {
+        assert_eq!(fold_literal(&Net::N(3), "01"), Folded::Input(true));
+        assert_eq!(fold_literal(&Net::N(3), "10"), Folded::Input(false));
+        assert_eq!(fold_literal(&Net::LiteralOne, "10"), Folded::True);
+        assert_eq!(fold_literal(&Net::LiteralOne, "01"), Folded::False);
+        assert_eq!(fold_literal(&Net::LiteralZero, "01"), Folded::True);
+        assert_eq!(fold_literal(&Net::NotConnected, "00"), Folded::True);
+        assert_eq!(fold_literal(&Net::NotConnected, "10"), Folded::False);
+    }
+
+    #[test]
+    fn test_auto_assign() -> Result<()> {
+        let g = testcase("synth_olmc_test");
+
+        let pcf = auto_assign(&g, Chip::GAL16V8)?;
+        assert_eq!(pcf.pin("clk"), Some(1));
+        for port in &g.ports {
+            let pin = port.lookup(&pcf).expect("every port should get a pin");
+            match port.direction {
+                PortDirection::Input if port.name != "clk" => {
+                    assert!((2..=9).contains(&pin), "{} on {pin}", port.name)
+                }
+                PortDirection::Output => assert!((12..=19).contains(&pin)),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Two registered outputs, q and r, of input d. q is enabled by oe being low, and r
+    /// by `r_enable`.
+    fn oe_graph(r_enable: Net) -> Graph {
+        let mut g = Graph::default();
+        ports(
+            &mut g,
+            &[
+                ("clk", 1, PortDirection::Input),
+                ("d", 2, PortDirection::Input),
+                ("oe", 3, PortDirection::Input),
+                ("q", 20, PortDirection::Output),
+                ("r", 21, PortDirection::Output),
+            ],
+        );
+        for (a, y) in [(2, 12), (3, 13)] {
+            g.nodelist.push(input(a, y));
+        }
+        g.nodelist.push(sop(&[13], 14, "01"));
+        g.nodelist.push(sop(&[12], 15, "10"));
+        for (y, e) in [(20, Net::N(14)), (21, r_enable)] {
+            g.nodelist.push(olmc(15, e, y, true));
+        }
+        g.generate_adjacency();
+        g
+    }
+
+    #[test]
+    fn test_registered_enable() -> Result<()> {
+        let g = oe_graph(Net::N(14));
+        let oe = registered_enable(&g, &PcfFile::default())?;
+        assert_eq!(oe.map(|p| p.name.as_str()), Some("oe"));
+        assert_eq!(auto_assign(&g, Chip::GAL16V8)?.pin("oe"), Some(11));
+
+        let err = registered_enable(&g, &parse_pcf("set_io oe 5")).unwrap_err();
+        assert!(matches!(&err, MappingError::RegisteredEnable(q) if q == "q"), "{err}");
+
+        let g = oe_graph(Net::LiteralOne);
+        let err = registered_enable(&g, &PcfFile::default()).unwrap_err();
+        assert!(
+            matches!(&err, MappingError::MixedEnables(q, r) if q == "q" && r == "r"),
+            "{err}"
+        );
+        Ok(())
+    }
+
+    /// Like `testcases/inout_tristate.v`: y is driven by a & b when c & b, and z = y & c & w
+    /// reads it back. w is an inout port that nothing drives.
+    fn inout_graph(y_enable: Net, registered: bool) -> Graph {
+        let mut g = Graph::default();
+        ports(
+            &mut g,
+            &[
+                ("clk", 1, PortDirection::Input),
+                ("a", 2, PortDirection::Input),
+                ("b", 3, PortDirection::Input),
+                ("c", 4, PortDirection::Input),
+                ("y", 20, PortDirection::Inout),
+                ("z", 21, PortDirection::Output),
+                ("w", 22, PortDirection::Inout),
+            ],
+        );
+        g.nodelist.push(input(2, 12));
+        g.nodelist.push(input(3, 13));
+        g.nodelist.push(input(4, 14));
+        g.nodelist.push(sop(&[14, 13], 15, "1010"));
+        g.nodelist.push(sop(&[12, 13], 16, "1010"));
+        g.nodelist.push(olmc(16, y_enable, 20, registered));
+        g.nodelist.push(sop(&[20, 14, 22], 17, "101010"));
+        g.nodelist.push(olmc(17, Net::LiteralOne, 21, false));
+        g.generate_adjacency();
+        g
+    }
+
+    #[test]
+    fn test_inout() -> Result<()> {
+        let pcf = parse_pcf(
+            "set_io clk 1\nset_io a 2\nset_io b 3\nset_io c 4\n\
+             set_io y 12\nset_io z 13\nset_io w 14",
+        );
+        let fit = fit(&inout_graph(Net::N(15), false), &pcf, Chip::GAL16V8)?;
+        // w's row is kept free for it, and y drives its pin only when enabled.
+        assert_eq!(fit.olmcmap[2], Some(NodeIdx(usize::MAX)));
+        assert!(fit.blueprint.olmcs[2].output.is_none());
+        let tri = fit.blueprint.olmcs[0].tri_con.as_ref().expect("y has an enable");
+        let mut pins: Vec<usize> = tri.pins[0].iter().map(|p| p.pin).collect();
+        pins.sort();
+        assert_eq!(pins, [3, 4]);
+        // z reads y and w from their pins.
+        let (_, z) = fit.blueprint.olmcs[1].output.as_ref().expect("z is an output");
+        let mut pins: Vec<usize> = z.pins[0].iter().map(|p| p.pin).collect();
+        pins.sort();
+        assert_eq!(pins, [4, 12, 14]);
+
+        let err = check_inouts(&inout_graph(Net::LiteralOne, false)).unwrap_err();
+        assert!(matches!(&err, MappingError::InoutAlwaysEnabled(y) if y == "y"), "{err}");
+        let err = check_inouts(&inout_graph(Net::N(15), true)).unwrap_err();
+        assert!(matches!(&err, MappingError::InoutRegistered(y) if y == "y"), "{err}");
         Ok(())
     }
 }
@@ -0,0 +1,625 @@
+//! Helpers for working with JEDEC fuse files: reading them, describing what each fuse does,
+//! and editing the text.
+
+use crate::device::{self, Device};
+use galette::chips::Chip;
+use galette::gal::GAL;
+use galette::writer::{make_jedec, Config};
+use thiserror::Error;
+
+const STX: char = '\x02';
+const ETX: char = '\x03';
+
+/// The transmission checksum: the 16-bit sum of every byte from STX to ETX inclusive.
+pub fn transmission_checksum(text: &str) -> Option<u16> {
+    let start = text.find(STX)?;
+    let end = text.find(ETX)?;
+    Some(
+        text.as_bytes()[start..=end]
+            .iter()
+            .fold(0u16, |sum, b| sum.wrapping_add(*b as u16)),
+    )
+}
+
+/// Replace the transmission checksum after ETX with one computed for the current text.
+/// A file without a checksum field after ETX is left alone.
+fn fix_transmission_checksum(text: &str) -> String {
+    let (Some(end), Some(sum)) = (text.find(ETX), transmission_checksum(text)) else {
+        return text.to_string();
+    };
+    let after = &text[end + 1..];
+    if after.len() < 4 || !after[..4].chars().all(|c| c.is_ascii_hexdigit()) {
+        return text.to_string();
+    }
+    format!("{}{sum:04X}{}", &text[..=end], &after[4..])
+}
+
+/// Add `V` test vector fields to a JEDEC file, along with the `QV` vector count.
+/// The vectors go just before the fuse checksum, and the transmission checksum is updated.
+pub fn add_vectors(jed: &str, vectors: &[String]) -> String {
+    if vectors.is_empty() {
+        return jed.to_string();
+    }
+    let newline = if jed.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut fields = String::new();
+    for (i, v) in vectors.iter().enumerate() {
+        fields += &format!("V{:04} {v}*{newline}", i + 1);
+    }
+
+    // fields start at the beginning of a line, and only count after STX, as the header
+    // before it is free text.
+    let body = jed.find(STX).map_or(0, |i| i + 1);
+    let find_line = |from: usize, prefix: &str| {
+        jed[from..]
+            .find(&format!("\n{prefix}"))
+            .map(|i| from + i + 1)
+    };
+    let mut at = 0;
+    let mut out = String::new();
+    if let Some(qf) = find_line(body, "QF") {
+        let end = jed[qf..].find('\n').map_or(jed.len(), |i| qf + i + 1);
+        out += &jed[..end];
+        out += &format!("QV{}*{newline}", vectors.len());
+        at = end;
+    }
+    let insert = find_line(at.max(body), "C")
+        .or_else(|| jed[at..].find(ETX).map(|i| at + i))
+        .unwrap_or(jed.len());
+    out += &jed[at..insert];
+    out += &fields;
+    out += &jed[insert..];
+    fix_transmission_checksum(&out)
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum JedecError {
+    #[error("No STX found")]
+    MissingStx,
+
+    #[error("No ETX found")]
+    MissingEtx,
+
+    #[error("No QF fuse count field")]
+    MissingFuseCount,
+
+    #[error("Bad {0} field: {1}")]
+    BadField(char, String),
+
+    #[error("Fuse {0} is past the end of the fuse map")]
+    FuseOutOfRange(usize),
+
+    #[error("Fuse checksum is {found:04X}, but the fuses sum to {expected:04X}")]
+    FuseChecksum { found: u16, expected: u16 },
+
+    #[error("Transmission checksum is {found:04X}, but the text sums to {expected:04X}")]
+    TransmissionChecksum { found: u16, expected: u16 },
+}
+
+/// The contents of a JEDEC fuse file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Jedec {
+    pub fuse_count: usize,
+    pub pin_count: Option<usize>,
+    pub fuses: Vec<bool>,
+    /// The `C` fuse checksum field, if there was one.
+    pub checksum: Option<u16>,
+    /// The transmission checksum after ETX, if there was one.
+    pub transmission: Option<u16>,
+    pub notes: Vec<String>,
+    pub vectors: Vec<String>,
+}
+
+/// The fuse checksum: the 16-bit sum of the fuses packed into bytes, fuse 0 in the low bit.
+pub fn fuse_checksum(fuses: &[bool]) -> u16 {
+    fuses.chunks(8).fold(0u16, |sum, byte| {
+        let b = byte
+            .iter()
+            .enumerate()
+            .fold(0u16, |b, (i, f)| b | (*f as u16) << i);
+        sum.wrapping_add(b)
+    })
+}
+
+fn parse_num(field: &str, kind: char, radix: u32) -> Result<usize, JedecError> {
+    usize::from_str_radix(field.trim(), radix)
+        .map_err(|_| JedecError::BadField(kind, field.to_string()))
+}
+
+/// Parse a JEDEC file, checking the fuse checksum and the transmission checksum. A
+/// transmission checksum of `0000` is a placeholder and is not checked. The design
+/// specification, from STX up to the first `*`, is free text and is skipped.
+pub fn parse(text: &str) -> Result<Jedec, JedecError> {
+    let start = text.find(STX).ok_or(JedecError::MissingStx)?;
+    let end = text.find(ETX).ok_or(JedecError::MissingEtx)?;
+    if end < start {
+        return Err(JedecError::MissingEtx);
+    }
+
+    let mut jed = Jedec::default();
+    let mut default = false;
+    let mut links: Vec<(usize, String)> = Vec::new();
+    for field in text[start + 1..end].split('*').skip(1) {
+        let field = field.trim_start();
+        let mut chars = field.chars();
+        let Some(kind) = chars.next() else {
+            continue;
+        };
+        let rest = chars.as_str();
+        match kind {
+            'Q' => {
+                let mut rest = rest.chars();
+                let which = rest.next();
+                let value = rest.as_str();
+                match which {
+                    Some('F') => jed.fuse_count = parse_num(value, 'Q', 10)?,
+                    Some('P') => jed.pin_count = Some(parse_num(value, 'Q', 10)?),
+                    // QV and any other counts we don't use.
+                    Some(c) if c.is_ascii_uppercase() => {}
+                    _ => return Err(JedecError::BadField('Q', field.to_string())),
+                }
+            }
+            'F' => default = parse_num(rest, 'F', 10)? != 0,
+            'L' => {
+                let rest = rest.trim_start();
+                let split = rest.find(|c: char| c.is_whitespace()).unwrap_or(rest.len());
+                let addr = parse_num(&rest[..split], 'L', 10)?;
+                links.push((addr, rest[split..].to_string()));
+            }
+            'C' => jed.checksum = Some(parse_num(rest, 'C', 16)? as u16),
+            'N' => jed.notes.push(rest.trim().to_string()),
+            'V' => jed.vectors.push(rest.trim().to_string()),
+            // other fields (device, security, and so on) don't affect the fuse map.
+            _ => {}
+        }
+    }
+    if jed.fuse_count == 0 {
+        return Err(JedecError::MissingFuseCount);
+    }
+
+    jed.fuses = vec![default; jed.fuse_count];
+    for (addr, bits) in links {
+        let digits = bits.chars().filter(|c| !c.is_whitespace());
+        for (i, c) in (addr..).zip(digits) {
+            let fuse = jed.fuses.get_mut(i).ok_or(JedecError::FuseOutOfRange(i))?;
+            *fuse = match c {
+                '0' => false,
+                '1' => true,
+                _ => return Err(JedecError::BadField('L', bits)),
+            };
+        }
+    }
+
+    if let Some(found) = jed.checksum {
+        let expected = fuse_checksum(&jed.fuses);
+        if found != expected {
+            return Err(JedecError::FuseChecksum { found, expected });
+        }
+    }
+    let after = text[end + 1..].get(..4).filter(|a| a.chars().all(|c| c.is_ascii_hexdigit()));
+    if let Some(after) = after {
+        let found = u16::from_str_radix(after, 16).unwrap();
+        jed.transmission = Some(found);
+        let expected = transmission_checksum(text).unwrap();
+        if found != 0 && found != expected {
+            return Err(JedecError::TransmissionChecksum { found, expected });
+        }
+    }
+    Ok(jed)
+}
+
+/// The JEDEC file galette writes for the base chip.
+pub fn galette_jedec(gal: &GAL) -> String {
+    let config = Config {
+        gen_pin: false,
+        gen_fuse: false,
+        gen_chip: false,
+        jedec_sec_bit: false,
+    };
+    make_jedec(&config, gal)
+}
+
+/// A fuse map in the binary format `jedutil -convert` writes: the fuse count as a 32-bit
+/// big-endian number, then the fuses packed into bytes with fuse 0 in the low bit.
+pub fn to_bin(fuses: &[bool]) -> Vec<u8> {
+    let mut bin = (fuses.len() as u32).to_be_bytes().to_vec();
+    bin.extend(fuses.chunks(8).map(|byte| {
+        byte.iter()
+            .enumerate()
+            .fold(0u8, |b, (i, f)| b | (*f as u8) << i)
+    }));
+    bin
+}
+
+/// Bytes as hex, one per line, for `$readmemh`. This is what `xxd -ps -c 1` writes.
+pub fn to_hex(bin: &[u8]) -> String {
+    bin.iter().map(|b| format!("{b:02x}\n")).collect()
+}
+
+/// The chip with this many fuses, for files that don't say which chip they are for.
+pub fn chip_for_fuse_count(fuse_count: usize) -> Option<Chip> {
+    device::for_fuse_count(fuse_count).map(|d| d.chip)
+}
+
+/// Adapt a JEDEC file written for a base chip to a variant of it. The header names the
+/// variant instead of the chip, and the variant's extra fuses follow the base map, with the
+/// fuse count and both checksums updated.
+pub fn for_device(jed: &str, device: &Device, extra: &[bool]) -> Result<String, JedecError> {
+    let newline = if jed.contains("\r\n") { "\r\n" } else { "\n" };
+    let body = jed.find(STX).map_or(0, |i| i + 1);
+    let header_end = jed[body..].find('*').map_or(body, |i| body + i);
+    let mut out = jed.to_string();
+    if let Some(i) = jed[body..header_end].find(device.chip.name()) {
+        out.replace_range(body + i..body + i + device.chip.name().len(), device.name);
+    }
+    if extra.is_empty() {
+        return Ok(fix_transmission_checksum(&out));
+    }
+
+    let base = parse(jed)?;
+    let mut fuses = base.fuses.clone();
+    fuses.extend(extra);
+    out = out.replacen(
+        &format!("QF{}", base.fuse_count),
+        &format!("QF{}", fuses.len()),
+        1,
+    );
+    let bits: String = extra.iter().map(|f| if *f { '1' } else { '0' }).collect();
+    let field = format!("L{:04} {bits}", base.fuse_count);
+    let end = out.find(ETX).ok_or(JedecError::MissingEtx)?;
+    match base.checksum.and_then(|sum| out[..end].rfind(&format!("C{sum:04X}"))) {
+        Some(at) => {
+            let sum = fuse_checksum(&fuses);
+            out.replace_range(at..at + 5, &format!("C{sum:04X}"));
+            // fields either end with `*` or, as galette writes them, start with it.
+            if out[..at].ends_with('*') {
+                out.insert_str(at, &format!("{field}{newline}*"));
+            } else {
+                out.insert_str(at, &format!("{field}*{newline}"));
+            }
+        }
+        None => out.insert_str(end, &format!("{field}*{newline}")),
+    }
+    Ok(fix_transmission_checksum(&out))
+}
+
+/// Tidy up a JEDEC file read back from a programmer. minipro leaves the `*` off the last
+/// field and writes a transmission checksum that doesn't match, so the file ends with a
+/// terminated field and a recomputed checksum.
+pub fn normalise_readback(text: &str) -> String {
+    let Some(end) = text.find(ETX) else {
+        return text.to_string();
+    };
+    let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut out = text[..end].trim_end().to_string();
+    if !out.ends_with('*') && !out.ends_with(STX) {
+        out.push('*');
+    }
+    out += newline;
+    out.push(ETX);
+    let sum = transmission_checksum(&out).unwrap_or(0);
+    format!("{out}{sum:04X}{newline}")
+}
+
+/// The 16V8 array inputs in column order. Each takes two columns: the true literal, then
+/// the complement.
+pub const GAL16V8_COLUMNS: [u32; 16] = [2, 19, 3, 18, 4, 17, 5, 16, 6, 15, 7, 14, 8, 13, 9, 12];
+
+pub const GAL22V10_COLUMNS: [u32; 22] = [
+    1, 23, 2, 22, 3, 21, 4, 20, 5, 19, 6, 18, 7, 17, 8, 16, 9, 15, 10, 14, 11, 13,
+];
+
+/// Product terms for each 22V10 OLMC, from pin 23 down, not counting the output enable.
+pub const GAL22V10_PRODUCTS: [usize; 10] = [8, 10, 12, 14, 16, 16, 14, 12, 10, 8];
+
+fn describe_column(columns: &[u32], col: usize) -> String {
+    let inverted = if col % 2 == 1 { " inverted" } else { "" };
+    format!("input pin {}{inverted}", columns[col / 2])
+}
+
+/// Describe what a fuse does, for reporting differences between fuse maps. This follows the
+/// maps in the simulation models: the 16V8 in registered mode, and the 22V10.
+pub fn describe_fuse(chip: Chip, fuse: usize) -> String {
+    match chip {
+        Chip::GAL16V8 => describe_16v8(fuse),
+        Chip::GAL22V10 => describe_22v10(fuse),
+        _ => format!("fuse {fuse}"),
+    }
+}
+
+fn describe_16v8(fuse: usize) -> String {
+    let product = |row: usize| format!("row {row} (pin {} product {})", 19 - row / 8, row % 8);
+    match fuse {
+        0..=2047 => format!(
+            "{} {}",
+            product(fuse / 32),
+            describe_column(&GAL16V8_COLUMNS, fuse % 32)
+        ),
+        2048..=2055 => format!("pin {} XOR", 19 - (fuse - 2048)),
+        2056..=2119 => format!("signature bit {}", fuse - 2056),
+        2120..=2127 => format!("pin {} AC1", 19 - (fuse - 2120)),
+        2128..=2191 => format!("{} enable", product(fuse - 2128)),
+        2192 => "SYN".to_string(),
+        2193 => "AC0".to_string(),
+        _ => format!("fuse {fuse} (past the end of the map)"),
+    }
+}
+
+fn describe_22v10(fuse: usize) -> String {
+    let (row, col) = (fuse / 44, fuse % 44);
+    let input = describe_column(&GAL22V10_COLUMNS, col);
+    match fuse {
+        0..=43 => format!("row 0 (AR) {input}"),
+        44..=5763 => {
+            let mut first = 1;
+            for (i, products) in GAL22V10_PRODUCTS.iter().enumerate() {
+                let pin = 23 - i;
+                if row == first {
+                    return format!("row {row} (pin {pin} output enable) {input}");
+                }
+                if row <= first + products {
+                    return format!("row {row} (pin {pin} product {}) {input}", row - first - 1);
+                }
+                first += products + 1;
+            }
+            unreachable!()
+        }
+        5764..=5807 => format!("row {row} (SP) {input}"),
+        5808..=5827 => {
+            let pin = 23 - (fuse - 5808) / 2;
+            if fuse.is_multiple_of(2) {
+                format!("pin {pin} XOR")
+            } else {
+                format!("pin {pin} S1 (registered)")
+            }
+        }
+        5828..=5891 => format!("signature bit {}", fuse - 5828),
+        5892 => "power-down enable (ATF22V10C)".to_string(),
+        _ => format!("fuse {fuse} (past the end of the map)"),
+    }
+}
+
+/// Compare two fuse maps, describing each fuse that differs.
+pub fn diff(chip: Chip, a: &Jedec, b: &Jedec) -> Vec<String> {
+    let mut out = Vec::new();
+    if a.fuse_count != b.fuse_count {
+        out.push(format!(
+            "fuse counts differ: {} and {}",
+            a.fuse_count, b.fuse_count
+        ));
+    }
+    for (i, (x, y)) in a.fuses.iter().zip(&b.fuses).enumerate() {
+        if x != y {
+            out.push(format!(
+                "fuse {i}: {} -> {}, {}",
+                *x as u8,
+                *y as u8,
+                describe_fuse(chip, i)
+            ));
+        }
+    }
+    out
+}
+
+/// Compare a device's JEDEC file with another file or a readback of it. A readback stops
+/// short of the fuses the programmer can't read, so only the fuses it has are compared.
+pub fn compare(device: &Device, want: &Jedec, got: &Jedec) -> Vec<String> {
+    if got.fuse_count == device.readback_fuse_count && want.fuse_count == device.fuse_count {
+        let mut want = want.clone();
+        want.fuses.truncate(got.fuse_count);
+        want.fuse_count = got.fuse_count;
+        return diff(device.chip, &want, got);
+    }
+    diff(device.chip, want, got)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_vectors() {
+        let jed = "\x02\nQP20*\nQF2194*\nL0000 0101*\nC0002*\n\x030000\n";
+        let out = add_vectors(jed, &["C01XN".to_string(), "C10XN".to_string()]);
+        let body =
+            "\x02\nQP20*\nQF2194*\nQV2*\nL0000 0101*\nV0001 C01XN*\nV0002 C10XN*\nC0002*\n\x03";
+        assert!(out.starts_with(body), "{out:?}");
+        let sum = body.bytes().map(|b| b as u16).sum::<u16>();
+        assert_eq!(out, format!("{body}{sum:04X}\n"));
+    }
+
+    #[test]
+    fn test_parse() {
+        let body = "\x02\nGAL16V8*\nQP20* QF2194*\nF0*\nL0000 1101*\nL2192 11*\nN note*\nC";
+        let fuses = {
+            let mut f = vec![false; 2194];
+            f[0] = true;
+            f[1] = true;
+            f[3] = true;
+            f[2192] = true;
+            f[2193] = true;
+            f
+        };
+        let sum = fuse_checksum(&fuses);
+        // 0x0b for the first byte and 0x03 for the last.
+        assert_eq!(sum, 0x0e);
+        let text = format!("header\n{body}{sum:04X}*\n\x03");
+        let jed = parse(&format!("{text}0000\n")).unwrap();
+        assert_eq!(jed.fuse_count, 2194);
+        assert_eq!(jed.pin_count, Some(20));
+        assert_eq!(jed.fuses, fuses);
+        assert_eq!(jed.notes, vec!["note"]);
+
+        let good = fix_transmission_checksum(&format!("{text}0000\n"));
+        assert!(parse(&good).is_ok());
+        assert!(matches!(
+            parse(&format!("{text}1234\n")),
+            Err(JedecError::TransmissionChecksum { found: 0x1234, .. })
+        ));
+        assert!(matches!(
+            parse(&text.replace("L0000 1101", "L0000 1111")),
+            Err(JedecError::FuseChecksum { .. })
+        ));
+        assert_eq!(
+            parse(&text.replace("L2192 11", "L2193 11")),
+            Err(JedecError::FuseOutOfRange(2194))
+        );
+        // anything after ETX that isn't a checksum is ignored.
+        assert!(parse(&format!("{text}\u{e9}\u{e9}\n")).is_ok());
+        assert_eq!(
+            parse(&text.replace("QP20", "Q\u{e9}20")),
+            Err(JedecError::BadField('Q', "Q\u{e9}20".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_cupl_header() {
+        // WinCUPL puts the design details before the first `*`, and starts fields with `*`.
+        let mut fuses = vec![false; 2194];
+        fuses[2192] = true;
+        let sum = fuse_checksum(&fuses);
+        let text = format!(
+            "\x02\n\
+CUPL(WM)        5.0a Serial# 60008009\n\
+Device          g16v8  Library DLIB-h-40-1\n\
+Created         Sat Jan 01 00:00:00 2000\n\
+Name            and_gate\n\
+Partno          00\n\
+Revision        01\n\
+Date            00/00/00\n\
+Designer        ver2gal\n\
+Company         None\n\
+Assembly        None\n\
+Location        None\n\
+*QP20\n\
+*QF2194\n\
+*G0\n\
+*F0\n\
+*L02192 10\n\
+*C{sum:04X}\n\
+*\x030000\n"
+        );
+        let jed = parse(&text).unwrap();
+        assert_eq!(jed.fuse_count, 2194);
+        assert_eq!(jed.pin_count, Some(20));
+        assert_eq!(jed.fuses, fuses);
+        assert_eq!(jed.checksum, Some(sum));
+    }
+
+    #[test]
+    fn test_bin() {
+        // the GAL16V8 array, XOR, signature and AC1 fuses, then PTD, SYN and AC0.
+        let mut fuses = vec![false; 2128];
+        fuses[0] = true;
+        fuses[9] = true;
+        fuses.extend([true; 64]);
+        fuses.extend([true, false]);
+        let bin = to_bin(&fuses);
+        assert_eq!(bin.len(), 279);
+        assert_eq!(&bin[..6], &[0x00, 0x00, 0x08, 0x92, 0x01, 0x02]);
+        // the product term enables fill bytes 266 to 273, after the header, then SYN.
+        assert_eq!(
+            &bin[269..],
+            &[0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]
+        );
+        assert!(to_hex(&bin).starts_with("00\n00\n08\n92\n01\n02\n"));
+        assert_eq!(to_hex(&bin).lines().count(), 279);
+
+        assert_eq!(to_bin(&[false; 5892]).len(), 741);
+    }
+
+    #[test]
+    fn test_normalise_readback() {
+        let text = "\x02\r\nGAL16V8*\r\nQF2194*\r\nL0000 1*\r\nC0001\r\n\x03ABCD";
+        assert!(parse(text).is_err());
+        let fixed = normalise_readback(text);
+        assert!(fixed.starts_with("\x02\r\nGAL16V8*\r\nQF2194*\r\nL0000 1*\r\nC0001*\r\n\x03"));
+        assert!(fixed.ends_with("\r\n"));
+        let jed = parse(&fixed).unwrap();
+        assert_eq!(jed.checksum, Some(1));
+        assert_eq!(jed.transmission, transmission_checksum(&fixed));
+    }
+
+    #[test]
+    fn test_for_device() {
+        let jed = "\x02Device: GAL22V10\n*QF5892*L0000 01*C0002*\x030000";
+        let atf = device::lookup("ATF22V10C").unwrap();
+        let out = for_device(jed, atf, &[true]).unwrap();
+        assert!(
+            out.starts_with("\x02Device: ATF22V10C\n*QF5893*L0000 01*L5892 1\n*C0012*\x03"),
+            "{out:?}"
+        );
+        let parsed = parse(&out).unwrap();
+        assert_eq!(parsed.fuse_count, 5893);
+        assert!(parsed.fuses[5892]);
+        assert_eq!(parsed.transmission, transmission_checksum(&out));
+
+        // a part without extra fuses only changes the name.
+        let jed = "\x02Device: GAL16V8\nQF2194*\nC0000*\n\x03";
+        let out = for_device(jed, device::lookup("ATF16V8B").unwrap(), &[]).unwrap();
+        assert_eq!(out, "\x02Device: ATF16V8B\nQF2194*\nC0000*\n\x03");
+    }
+
+    #[test]
+    fn test_compare() {
+        let atf = device::lookup("ATF22V10C").unwrap();
+        let mut want = Jedec {
+            fuse_count: 5893,
+            fuses: vec![false; 5893],
+            ..Default::default()
+        };
+        want.fuses[5892] = true;
+        let mut got = Jedec {
+            fuse_count: 5892,
+            fuses: vec![false; 5892],
+            ..Default::default()
+        };
+        // the power-down fuse isn't read back.
+        assert!(compare(atf, &want, &got).is_empty());
+        got.fuses[5828] = true;
+        assert_eq!(compare(atf, &want, &got), ["fuse 5828: 0 -> 1, signature bit 0"]);
+        // two JEDEC files for the part are compared in full.
+        let other = Jedec {
+            fuse_count: 5893,
+            fuses: vec![false; 5893],
+            ..Default::default()
+        };
+        assert_eq!(compare(atf, &want, &other).len(), 1);
+    }
+
+    #[test]
+    fn test_describe_fuse() {
+        assert_eq!(
+            describe_fuse(Chip::GAL16V8, 3 * 32 + 7),
+            "row 3 (pin 19 product 3) input pin 18 inverted"
+        );
+        assert_eq!(describe_fuse(Chip::GAL16V8, 2049), "pin 18 XOR");
+        assert_eq!(
+            describe_fuse(Chip::GAL16V8, 2128 + 9),
+            "row 9 (pin 18 product 1) enable"
+        );
+        assert_eq!(
+            describe_fuse(Chip::GAL22V10, 5),
+            "row 0 (AR) input pin 2 inverted"
+        );
+        assert_eq!(
+            describe_fuse(Chip::GAL22V10, 44 + 4),
+            "row 1 (pin 23 output enable) input pin 2"
+        );
+        assert_eq!(
+            describe_fuse(Chip::GAL22V10, 10 * 44 + 43),
+            "row 10 (pin 22 output enable) input pin 13 inverted"
+        );
+        assert_eq!(
+            describe_fuse(Chip::GAL22V10, 9 * 44),
+            "row 9 (pin 23 product 7) input pin 1"
+        );
+        assert_eq!(
+            describe_fuse(Chip::GAL22V10, 131 * 44),
+            "row 131 (SP) input pin 1"
+        );
+        assert_eq!(
+            describe_fuse(Chip::GAL22V10, 5827),
+            "pin 14 S1 (registered)"
+        );
+    }
+}
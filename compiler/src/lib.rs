@@ -1,3 +1,20 @@
 pub mod yosys_parser;
 pub mod pcf;
 pub mod fitter;
+pub mod sop;
+pub mod opt;
+pub mod partition;
+pub mod export;
+pub mod verilog;
+pub mod pld;
+pub mod cupl;
+pub mod terms;
+pub mod sim;
+pub mod jedec;
+pub mod program;
+pub mod sat;
+pub mod equiv;
+pub mod device;
+pub mod signature;
+#[cfg(test)]
+mod test_util;
@@ -1,21 +1,38 @@
+mod cupl;
+mod device;
+mod equiv;
+mod export;
 mod fitter;
+mod jedec;
+mod opt;
+mod partition;
 pub mod pcf;
+mod pld;
+mod program;
+mod sat;
+mod signature;
+mod sim;
+mod sop;
+mod terms;
+#[cfg(test)]
+mod test_util;
+mod verilog;
 pub mod yosys_parser;
 
-use crate::fitter::{graph_convert, MappingError};
+use crate::fitter::{auto_assign, graph_convert, Fit, MappingError};
 use crate::pcf::{parse_pcf, PcfFile};
 use crate::yosys_parser::{Graph, YosysDoc};
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 use clap::{Args, Parser, Subcommand, ValueEnum};
 use galette::blueprint::Blueprint;
 use galette::chips::Chip;
+use galette::gal::GAL;
 use galette::gal_builder::build;
-use galette::writer::{make_jedec, Config};
 use log::{info, trace, warn};
 use serde_json::from_slice;
 use std::fs::{self, File};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::str;
 
@@ -30,12 +47,30 @@ enum Commands {
     /// Validate a yosys netlist JSON file.
     Validate(ValidateArgs),
     Synth(SynthArgs),
+    /// Report which chips a netlist fits, without writing a JEDEC file.
+    Fit(FitArgs),
+    /// Split a netlist that is too large for one chip across several.
+    Partition(PartitionArgs),
+    /// Render the netlist as a graph, annotated with the placement if it fits.
+    Graph(GraphArgs),
+    /// Compare two JEDEC files fuse by fuse, describing what each difference does.
+    JedDiff(JedDiffArgs),
+    /// Program a chip with an external programmer, then read it back and verify it.
+    Program(ProgramArgs),
+    /// Prove a JEDEC file does what the netlist does, with the pins given by the constraints.
+    Prove(ProveArgs),
+    /// Show which chip a JEDEC file is for, and decode its signature.
+    Info(InfoArgs),
 }
 
 #[derive(Args)]
 struct ValidateArgs {
     #[arg(required = true, value_hint = clap::ValueHint::DirPath)]
     file: PathBuf,
+
+    /// Also check the design's inputs fit on this chip.
+    #[arg(value_enum, long)]
+    chip: Option<ChipType>,
 }
 
 #[derive(ValueEnum, Debug, Clone)]
@@ -62,6 +97,295 @@ struct SynthArgs {
 
     #[arg(value_enum, long, default_value_t=ChipType::GAL16V8)]
     chip: ChipType,
+
+    /// The exact part: GAL16V8, ATF16V8B, ATF16V8C, GAL22V10, ATF22V10C or ATF22LV10.
+    /// Overrides --chip.
+    #[arg(long, value_parser = device::lookup, conflicts_with = "chip")]
+    device: Option<&'static device::Device>,
+
+    /// Enable the part's power-down pin, which then can't be used by the design.
+    #[arg(long)]
+    power_down: bool,
+
+    /// Write this into the signature row: up to 8 characters of text, 0x and up to 16 hex
+    /// digits, or `auto` for the short git commit hash, or the date outside a git checkout.
+    #[arg(long)]
+    signature: Option<String>,
+
+    /// Don't run the two-level minimiser on the SOPs before placement.
+    #[arg(long)]
+    no_minimise: bool,
+
+    /// Write the netlist as it is after optimisation, as yosys JSON.
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    dump_netlist: Option<PathBuf>,
+
+    /// Write a Verilog model of the fitted design, for simulation.
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    emit_verilog: Option<PathBuf>,
+
+    /// Write the fitted design as galette PLD source.
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    emit_pld: Option<PathBuf>,
+
+    /// Write the fitted design as CUPL source.
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    emit_cupl: Option<PathBuf>,
+
+    /// Write the fuse map in the binary format of `jedutil -convert`.
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    emit_bin: Option<PathBuf>,
+
+    /// Write the fuse map as hex, one byte per line, for the models in `models/`.
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    emit_hex: Option<PathBuf>,
+
+    /// Add up to this many test vectors, simulated from the fitted design, to the JEDEC file.
+    #[arg(long)]
+    vectors: Option<usize>,
+}
+
+/// The chip modes `fit` can evaluate. The fitter only drives the GAL16V8 in registered mode,
+/// so its simple and complex modes are listed as unsupported.
+#[derive(ValueEnum, Debug, Clone)]
+enum FitTarget {
+    Any,
+    #[value(name = "gal16v8-simple")]
+    GAL16V8Simple,
+    #[value(name = "gal16v8-complex")]
+    GAL16V8Complex,
+    #[value(name = "gal16v8-registered")]
+    GAL16V8Registered,
+    GAL22V10,
+}
+
+impl FitTarget {
+    /// The table label for each target, with the chip to fit it to, or `None` if the fitter
+    /// can't produce that mode.
+    fn targets(&self) -> Vec<(&'static str, Option<ChipType>)> {
+        match self {
+            Self::Any => [
+                Self::GAL16V8Simple,
+                Self::GAL16V8Complex,
+                Self::GAL16V8Registered,
+                Self::GAL22V10,
+            ]
+            .iter()
+            .flat_map(|t| t.targets())
+            .collect(),
+            Self::GAL16V8Simple => vec![("GAL16V8 (simple)", None)],
+            Self::GAL16V8Complex => vec![("GAL16V8 (complex)", None)],
+            Self::GAL16V8Registered => vec![("GAL16V8 (registered)", Some(ChipType::GAL16V8))],
+            Self::GAL22V10 => vec![("GAL22V10", Some(ChipType::GAL22V10))],
+        }
+    }
+}
+
+#[derive(Args)]
+struct FitArgs {
+    #[arg(required = true, value_hint = clap::ValueHint::DirPath)]
+    netlist: PathBuf,
+    /// Pin constraints to use for every target. Pins are assigned automatically if omitted.
+    #[arg(long, value_hint = clap::ValueHint::DirPath)]
+    pcf: Option<PathBuf>,
+
+    #[arg(value_enum, long, default_value_t=FitTarget::Any)]
+    chip: FitTarget,
+
+    /// Don't run the two-level minimiser on the SOPs before placement.
+    #[arg(long)]
+    no_minimise: bool,
+}
+
+#[derive(Args)]
+struct PartitionArgs {
+    #[arg(required = true, value_hint = clap::ValueHint::DirPath)]
+    netlist: PathBuf,
+
+    #[arg(value_enum, long, default_value_t=ChipType::GAL16V8)]
+    chip: ChipType,
+
+    /// Number of chips to use. Defaults to the fewest that fit.
+    #[arg(long)]
+    chips: Option<usize>,
+
+    /// Where to write the JEDEC files, pin reports and connection list.
+    #[arg(long, default_value = ".", value_hint = clap::ValueHint::DirPath)]
+    output_dir: PathBuf,
+
+    /// Don't run the two-level minimiser on the SOPs before placement.
+    #[arg(long)]
+    no_minimise: bool,
+}
+
+#[derive(ValueEnum, Debug, Clone)]
+enum GraphFormat {
+    Dot,
+    Json,
+}
+
+#[derive(Args)]
+struct GraphArgs {
+    #[arg(required = true, value_hint = clap::ValueHint::DirPath)]
+    netlist: PathBuf,
+    /// Pin constraints to fit with. Without them the graph is not annotated.
+    #[arg(long, value_hint = clap::ValueHint::DirPath)]
+    pcf: Option<PathBuf>,
+
+    #[arg(value_enum, long, default_value_t=ChipType::GAL16V8)]
+    chip: ChipType,
+
+    #[arg(value_enum, long, default_value_t=GraphFormat::Dot)]
+    format: GraphFormat,
+
+    /// Write the graph here instead of to stdout.
+    #[arg(short, long, value_hint = clap::ValueHint::FilePath)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct JedDiffArgs {
+    #[arg(required = true, value_hint = clap::ValueHint::FilePath)]
+    a: PathBuf,
+    #[arg(required = true, value_hint = clap::ValueHint::FilePath)]
+    b: PathBuf,
+
+    /// The chip the files are for. Defaults to the one matching the fuse count.
+    #[arg(value_enum, long)]
+    chip: Option<ChipType>,
+
+    /// The part the files are for, so a readback of it can be compared with its JEDEC file.
+    #[arg(long, value_parser = device::lookup, conflicts_with = "chip")]
+    device: Option<&'static device::Device>,
+}
+
+#[derive(ValueEnum, Debug, Clone)]
+enum ProgrammerType {
+    Minipro,
+}
+
+#[derive(Args)]
+struct ProgramArgs {
+    #[arg(required = true, value_hint = clap::ValueHint::FilePath)]
+    jedec: PathBuf,
+
+    /// Device name to pass to the programmer, e.g. ATF16V8B. The part is found from this or,
+    /// failing that, from the fuse count of the JEDEC file.
+    #[arg(long, required_unless_present = "device")]
+    chip: Option<String>,
+
+    /// The part being programmed, which gives the name to pass to the programmer and the fuse
+    /// counts to expect.
+    #[arg(long, value_parser = device::lookup, conflicts_with = "chip")]
+    device: Option<&'static device::Device>,
+
+    #[arg(value_enum, long, default_value_t=ProgrammerType::Minipro)]
+    programmer: ProgrammerType,
+
+    /// Command to write the chip, replacing the programmer's. {device} and {jed} are filled in.
+    #[arg(long)]
+    write_cmd: Option<String>,
+
+    /// Command to read the chip back, replacing the programmer's. {device} and {readback} are filled in.
+    #[arg(long)]
+    read_cmd: Option<String>,
+
+    /// Only read the chip back and verify it.
+    #[arg(long)]
+    verify_only: bool,
+}
+
+#[derive(Args)]
+struct ProveArgs {
+    #[arg(required = true, value_hint = clap::ValueHint::FilePath)]
+    netlist: PathBuf,
+    #[arg(required = true, value_hint = clap::ValueHint::FilePath)]
+    constraints: PathBuf,
+    #[arg(required = true, value_hint = clap::ValueHint::FilePath)]
+    jedec: PathBuf,
+
+    /// The most clock cycles to unroll, for the search and the induction.
+    #[arg(long, default_value_t = 8)]
+    depth: usize,
+}
+
+#[derive(Args)]
+struct InfoArgs {
+    #[arg(required = true, value_hint = clap::ValueHint::FilePath)]
+    jedec: PathBuf,
+}
+
+fn load_graph(netlist: &PathBuf) -> Result<Graph> {
+    let f = fs::read(netlist)?;
+    let data: YosysDoc = from_slice(f.as_slice())?;
+    let g = Graph::from(data);
+    if let Err(e) = g.validate() {
+        bail!(e.to_string());
+    }
+    Ok(g)
+}
+
+/// Run the pre-placement optimisations, printing the minimiser statistics.
+fn optimise(g: &mut Graph, minimise: bool) {
+    if minimise {
+        println!("Minimiser:");
+        for stat in opt::minimise_sops(g) {
+            println!("{}: {} -> {} products", stat.name, stat.before, stat.after);
+        }
+    }
+}
+
+/// Run the optimisations that depend on the target chip and its pin constraints.
+fn optimise_for_chip(g: &mut Graph, pcf: &PcfFile, chip: Chip) -> Result<(), MappingError> {
+    opt::collapse_buried(g, pcf, chip);
+    opt::optimise_polarity(g);
+    opt::honour_inits(g, chip)?;
+    Ok(())
+}
+
+fn fit(f: FitArgs) -> Result<()> {
+    let mut g = load_graph(&f.netlist)?;
+    optimise(&mut g, !f.no_minimise);
+    let pcf = match f.pcf {
+        Some(path) => Some(parse_pcf(str::from_utf8(&fs::read(path)?)?)),
+        None => None,
+    };
+
+    println!(
+        "{:<22} {:<11} {:>7} {:>7}  first error",
+        "target", "fits", "olmcs", "terms"
+    );
+    for (target, chip) in f.chip.targets() {
+        let Some(chip) = chip else {
+            println!(
+                "{:<22} {:<11} {:>7} {:>7}  the fitter only drives the GAL16V8 in registered mode",
+                target, "unsupported", "-", "-"
+            );
+            continue;
+        };
+        let gal = chip.to_galette();
+        let mut g = g.clone();
+        let res = optimise_for_chip(&mut g, pcf.as_ref().unwrap_or(&PcfFile::default()), gal)
+            .and_then(|_| match &pcf {
+                Some(pcf) => Ok(pcf.clone()),
+                None => auto_assign(&g, gal),
+            })
+            .and_then(|pcf| fitter::fit(&g, &pcf, gal));
+        match res {
+            Ok(fit) => {
+                let u = fit.utilisation(&g);
+                println!(
+                    "{:<22} {:<11} {:>7} {:>7}  -",
+                    target,
+                    "yes",
+                    format!("{}/{}", u.olmcs_used, u.olmcs_total),
+                    format!("{}/{}", u.terms_used, u.terms_total),
+                );
+            }
+            Err(e) => println!("{:<22} {:<11} {:>7} {:>7}  {e}", target, "no", "-", "-"),
+        }
+    }
+    Ok(())
 }
 
 fn validate(v: ValidateArgs) -> Result<()> {
@@ -70,9 +394,15 @@ fn validate(v: ValidateArgs) -> Result<()> {
     let data: YosysDoc = from_slice(f.as_slice())?;
 
     let g = Graph::from(data);
-    let res = g.validate().map_err(|x| x.to_string());
+    let res = match &v.chip {
+        Some(chip) => g.validate_for_chip(chip.to_galette()),
+        None => g.validate(),
+    };
     if let Err(e) = res {
-        bail!(e);
+        for problem in &e.0 {
+            println!("{problem}");
+        }
+        bail!("{} problems found", e.0.len());
     }
     println!("Validation Complete!");
     println!("Stats:");
@@ -81,19 +411,32 @@ fn validate(v: ValidateArgs) -> Result<()> {
     Ok(())
 }
 
-fn load_to_graph(netlist: &PathBuf, pcf: &PcfFile, chip: Chip) -> Result<Blueprint, MappingError> {
+fn load_to_graph(
+    netlist: &PathBuf,
+    pcf: &PcfFile,
+    chip: Chip,
+    minimise: bool,
+    dump: Option<&Path>,
+) -> Result<(Graph, Fit)> {
     info!("loading netlist...");
-    let f = fs::read(netlist).unwrap();
+    let f = fs::read(netlist)?;
 
-    let data: YosysDoc = from_slice(f.as_slice()).unwrap();
+    let data: YosysDoc = from_slice(f.as_slice())?;
 
-    let g = Graph::from(data);
-    g.validate().map_err(|x| x.to_string()).unwrap();
+    let mut g = Graph::from(data);
+    g.validate()?;
+    optimise(&mut g, minimise);
+    optimise_for_chip(&mut g, pcf, chip)?;
+    if let Some(path) = dump {
+        let doc = YosysDoc::from(&g);
+        fs::write(path, serde_json::to_string_pretty(&doc)?)?;
+    }
     println!("Validation Complete!");
     println!("Stats:");
     println!("Nodes: {}", g.nodelist.len());
     println!("Edges: {}", g.adjlist.len());
-    graph_convert(&g, pcf, chip)
+    let fit = fitter::fit(&g, pcf, chip)?;
+    Ok((g, fit))
 }
 
 fn synth(s: SynthArgs) -> Result<()> {
@@ -101,14 +444,24 @@ fn synth(s: SynthArgs) -> Result<()> {
     let pcf_file = &fs::read(s.constraints)?;
     let pcf_string = std::str::from_utf8(pcf_file)?;
     let pcf = parse_pcf(pcf_string);
+    let sig = s.signature.as_deref().map(signature::parse).transpose()?;
+    let device = s.device.unwrap_or_else(|| device::base(s.chip.to_galette()));
+    device.check_pins(&pcf, s.power_down)?;
+    let chip = device.chip;
 
-    let mut res = load_to_graph(&s.netlist, &pcf, s.chip.to_galette());
+    let mut res = load_to_graph(
+        &s.netlist,
+        &pcf,
+        chip,
+        !s.no_minimise,
+        s.dump_netlist.as_deref(),
+    );
 
-    while let Err(MappingError::SopTooBig {
-        ref name,
+    while let Some(MappingError::SopTooBig {
+        name,
         sop_size,
         wanted_size,
-    }) = res
+    }) = res.as_ref().err().and_then(|e| e.downcast_ref())
     {
         warn!("Sop too large, attempting to split {name}. cur={sop_size} want={wanted_size}");
         let mut yosys = Command::new("yosys");
@@ -126,29 +479,305 @@ fn synth(s: SynthArgs) -> Result<()> {
         trace!("Yosys stdout: ====== {}", str::from_utf8(&out.stdout).expect("hi"));
         trace!("Yosys stderr: ====== {}", str::from_utf8(&out.stderr).expect("hi"));
 
-        res = load_to_graph(&s.netlist, &pcf, s.chip.to_galette());
+        res = load_to_graph(
+            &s.netlist,
+            &pcf,
+            chip,
+            !s.no_minimise,
+            s.dump_netlist.as_deref(),
+        );
     }
 
-    let bp = res?;
+    let (g, mut fit) = res?;
+    if let Some(sig) = sig {
+        fit.blueprint.sig = sig;
+    }
+    if let Some(path) = &s.emit_verilog {
+        fs::write(path, verilog::to_verilog(&fit, &g))?;
+    }
+    if let Some(path) = &s.emit_pld {
+        fs::write(path, pld::to_pld(&fit, &g))?;
+    }
+    if let Some(path) = &s.emit_cupl {
+        let src = cupl::to_cupl(&fit, &g);
+        if let Err(e) = cupl::check(&fit, &src) {
+            bail!("CUPL export does not match the fit: {e}");
+        }
+        fs::write(path, src)?;
+    }
+    let vectors = match s.vectors {
+        Some(n) => sim::test_vectors(&fit, &g, n),
+        None => Vec::new(),
+    };
+    let gal = build_gal(&fit.blueprint, chip)?;
+    if s.emit_bin.is_some() || s.emit_hex.is_some() {
+        let bin = jedec::to_bin(&jedec::parse(&jedec::galette_jedec(&gal))?.fuses);
+        if let Some(path) = &s.emit_bin {
+            fs::write(path, &bin)?;
+        }
+        if let Some(path) = &s.emit_hex {
+            fs::write(path, jedec::to_hex(&bin))?;
+        }
+    }
+    write_jedec(&gal, device, s.power_down, Path::new("output.jed"), &vectors)
+}
 
-    let mut gal = build(&bp)?;
+fn build_gal(bp: &Blueprint, chip: Chip) -> Result<GAL> {
+    let mut gal = build(bp)?;
 
-    if matches!(s.chip, ChipType::GAL16V8) {
+    if chip == Chip::GAL16V8 {
         gal.set_mode(galette::gal::Mode::Registered);
     }
+    Ok(gal)
+}
+
+/// Write the JEDEC file for a part, with its extra fuses after the chip's own.
+fn write_jedec(
+    gal: &GAL,
+    device: &device::Device,
+    power_down: bool,
+    path: &Path,
+    vectors: &[String],
+) -> Result<()> {
+    let mut file = File::create(path)?;
+    let jed = jedec::add_vectors(&jedec::galette_jedec(gal), vectors);
+    let jed = jedec::for_device(&jed, device, &device.extra_fuses(power_down)?)?;
+
+    file.write_all(jed.as_bytes())?;
+
+    Ok(())
+}
+
+/// Fit every chip of a partition, returning the blueprint and pin assignment for each.
+fn fit_partition(
+    p: &partition::Partition,
+    chip: Chip,
+) -> Result<Vec<(Blueprint, PcfFile)>, MappingError> {
+    p.devices
+        .iter()
+        .map(|dev| {
+            let mut dev = dev.clone();
+            optimise_for_chip(&mut dev, &PcfFile::default(), chip)?;
+            let pcf = auto_assign(&dev, chip)?;
+            let bp = graph_convert(&dev, &pcf, chip)?;
+            Ok((bp, pcf))
+        })
+        .collect()
+}
+
+fn partition(p: PartitionArgs) -> Result<()> {
+    let mut g = load_graph(&p.netlist)?;
+    optimise(&mut g, !p.no_minimise);
+    let chip = p.chip.to_galette();
+
+    let counts = match p.chips {
+        Some(n) => n..=n,
+        None => 1..=g.get_olmc_idx().len().max(1),
+    };
+    let mut last_err = None;
+    for n in counts {
+        let fitted = partition::partition(&g, chip, n)
+            .and_then(|part| Ok((fit_partition(&part, chip)?, part)));
+        let (fits, part) = match fitted {
+            Ok(x) => x,
+            Err(e) => {
+                info!("Partitioning into {n} chips failed: {e}");
+                last_err = Some(e);
+                continue;
+            }
+        };
+
+        fs::create_dir_all(&p.output_dir)?;
+        for (i, (bp, pcf)) in fits.iter().enumerate() {
+            let gal = build_gal(bp, chip)?;
+            let path = p.output_dir.join(format!("chip{i}.jed"));
+            write_jedec(&gal, device::base(chip), false, &path, &[])?;
+            fs::write(p.output_dir.join(format!("chip{i}.pcf")), pcf.to_string())?;
+        }
+        let mut conns = String::new();
+        for c in &part.cut {
+            let pin = |chip: usize| {
+                fits[chip]
+                    .1
+                    .pin(&c.name)
+                    .ok_or_else(|| anyhow!("chip{chip} has no pin for cut net {}", c.name))
+            };
+            let from_pin = pin(c.from)?;
+            for to in &c.to {
+                let to_pin = pin(*to)?;
+                conns += &format!(
+                    "chip{} pin {from_pin} -> chip{to} pin {to_pin} ({})\n",
+                    c.from, c.name
+                );
+            }
+        }
+        fs::write(p.output_dir.join("connections.txt"), &conns)?;
+        println!("Partitioned into {n} chips with {} cut nets:", part.cut.len());
+        print!("{conns}");
+        return Ok(());
+    }
+    match last_err {
+        Some(e) => bail!(e),
+        None => bail!("no chip counts to try"),
+    }
+}
 
-    let config = Config {
-        gen_pin: false,
-        gen_fuse: false,
-        gen_chip: false,
-        jedec_sec_bit: false,
+fn graph(a: GraphArgs) -> Result<()> {
+    let f = fs::read(&a.netlist)?;
+    let data: YosysDoc = from_slice(f.as_slice())?;
+    let g = Graph::from(data);
+    // this is for debugging broken netlists, so render them anyway.
+    if let Err(e) = g.validate() {
+        warn!("netlist has problems:\n{e}");
+    }
+
+    // fit an optimised copy the same way synth does, so the rendered graph keeps the cells
+    // that optimisation removes.
+    let fitted = match a.pcf {
+        Some(path) => {
+            let pcf = parse_pcf(str::from_utf8(&fs::read(path)?)?);
+            let chip = a.chip.to_galette();
+            let mut opt = g.clone();
+            opt::minimise_sops(&mut opt);
+            let res = optimise_for_chip(&mut opt, &pcf, chip).and_then(|_| fitter::fit(&opt, &pcf, chip));
+            match res {
+                Ok(fit) => Some((fit, opt)),
+                Err(e) => {
+                    warn!("could not fit the design, so it is not annotated: {e}");
+                    None
+                }
+            }
+        }
+        None => None,
     };
+    let fit = fitted.as_ref().map(|(fit, opt)| (fit, opt));
 
-    let mut file = File::create("output.jed")?;
-    let jed = make_jedec(&config, &gal);
+    let out = match a.format {
+        GraphFormat::Dot => export::to_dot(&g, fit),
+        GraphFormat::Json => serde_json::to_string_pretty(&export::to_json(&g, fit))?,
+    };
+    match a.output {
+        Some(path) => fs::write(path, out)?,
+        None => print!("{out}"),
+    }
+    Ok(())
+}
 
-    file.write_all(jed.as_bytes())?;
+fn read_jedec(path: &Path) -> Result<jedec::Jedec> {
+    let text = fs::read_to_string(path)?;
+    match jedec::parse(&text) {
+        Ok(jed) => Ok(jed),
+        Err(e) => bail!("{}: {e}", path.display()),
+    }
+}
 
+fn jed_diff(d: JedDiffArgs) -> Result<()> {
+    let a = read_jedec(&d.a)?;
+    let b = read_jedec(&d.b)?;
+    let device = match (d.device, d.chip) {
+        (Some(device), _) => device,
+        (None, Some(chip)) => device::base(chip.to_galette()),
+        (None, None) => match device::for_fuse_count(a.fuse_count) {
+            Some(device) => device,
+            None => bail!("no chip has {} fuses, pass --chip or --device", a.fuse_count),
+        },
+    };
+    let diffs = jedec::compare(device, &a, &b);
+    if diffs.is_empty() {
+        println!("Fuse maps match.");
+        return Ok(());
+    }
+    for line in &diffs {
+        println!("{line}");
+    }
+    bail!("VERIFICATION FAILED: {} differences", diffs.len())
+}
+
+fn program(p: ProgramArgs) -> Result<()> {
+    let mut programmer = match p.programmer {
+        ProgrammerType::Minipro => program::Programmer::minipro(),
+    };
+    if let Some(cmd) = p.write_cmd {
+        programmer.write = cmd;
+    }
+    if let Some(cmd) = p.read_cmd {
+        programmer.read = cmd;
+    }
+    let readback =
+        std::env::temp_dir().join(format!("ver2gal_readback_{}.jed", std::process::id()));
+    let (device, part) = match (p.device, p.chip.as_deref()) {
+        (Some(device), _) => (device, device.programmer_part),
+        (None, part) => {
+            let part = part.unwrap_or_default();
+            let device = match device::lookup(part) {
+                Ok(device) => device,
+                Err(_) => {
+                    let fuse_count = read_jedec(&p.jedec)?.fuse_count;
+                    match device::for_fuse_count(fuse_count) {
+                        Some(device) => device,
+                        None => bail!("no chip has {fuse_count} fuses, pass --device"),
+                    }
+                }
+            };
+            (device, part)
+        }
+    };
+    let res = program::program(&programmer, part, device, &p.jedec, &readback, !p.verify_only);
+    let _ = fs::remove_file(&readback);
+    let diffs = res?;
+    if diffs.is_empty() {
+        println!("VERIFICATION OK!");
+        return Ok(());
+    }
+    for line in &diffs {
+        println!("{line}");
+    }
+    bail!("VERIFICATION FAILED: {} differences", diffs.len())
+}
+
+fn prove(p: ProveArgs) -> Result<()> {
+    let g = load_graph(&p.netlist)?;
+    let pcf = parse_pcf(str::from_utf8(&fs::read(&p.constraints)?)?);
+    let jed = read_jedec(&p.jedec)?;
+    match equiv::prove(&g, &pcf, &jed.fuses, p.depth)? {
+        equiv::Verdict::Equivalent(k) => {
+            println!("Equivalent (proven by induction over {k} cycles).");
+            Ok(())
+        }
+        equiv::Verdict::Unknown(k) => {
+            bail!("No mismatch within {k} cycles of power-up, but equivalence could not be proven")
+        }
+        equiv::Verdict::Mismatch(cex) => {
+            print!("{cex}");
+            bail!("The JEDEC file does not match the netlist")
+        }
+    }
+}
+
+fn info(i: InfoArgs) -> Result<()> {
+    // this may be a programmer readback, so tidy it up first.
+    let text = jedec::normalise_readback(&fs::read_to_string(&i.jedec)?);
+    let jed = match jedec::parse(&text) {
+        Ok(jed) => jed,
+        Err(e) => bail!("{}: {e}", i.jedec.display()),
+    };
+    let devices = device::for_any_fuse_count(jed.fuse_count);
+    let Some(first) = devices.first() else {
+        bail!("no chip has {} fuses", jed.fuse_count);
+    };
+    let names: Vec<String> = devices
+        .iter()
+        .map(|d| match d.fuse_count == jed.fuse_count {
+            true => d.name.to_string(),
+            false => format!("{} readback", d.name),
+        })
+        .collect();
+    println!("Fuses: {} ({})", jed.fuse_count, names.join(", "));
+    if let Some(sum) = jed.checksum {
+        println!("Checksum: {sum:04X}");
+    }
+    let sig = signature::from_fuses(&jed.fuses[first.ues.clone()]);
+    println!("Signature: {}", signature::describe(&sig));
     Ok(())
 }
 
@@ -158,5 +787,12 @@ fn main() -> Result<()> {
     match args.command {
         Commands::Validate(v) => validate(v),
         Commands::Synth(s) => synth(s),
+        Commands::Fit(f) => fit(f),
+        Commands::Partition(p) => partition(p),
+        Commands::Graph(a) => graph(a),
+        Commands::JedDiff(d) => jed_diff(d),
+        Commands::Program(p) => program(p),
+        Commands::Prove(p) => prove(p),
+        Commands::Info(i) => info(i),
     }
 }
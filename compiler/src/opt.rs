@@ -0,0 +1,452 @@
+//! Netlist optimisation passes that run on the `Graph` before fitting.
+use crate::fitter::{power_up_level, row_capacity, MappingError};
+use crate::pcf::PcfFile;
+use crate::sop::{cube_and, Cover, Literal};
+use crate::yosys_parser::{GalSop, Graph, Net, Node, NodeIdx};
+use galette::chips::Chip;
+use log::{debug, info, warn};
+
+/// Find the SOP node driving the given port of an OLMC, if the SOP drives nothing else.
+fn exclusive_sop_for_olmc(graph: &Graph, olmc: &NodeIdx, port: &str) -> Option<NodeIdx> {
+    let sop_idx = graph
+        .get_node_port_conns(olmc, port)
+        .iter()
+        .filter_map(|adj| adj.get_other(olmc, port))
+        .find(|(idx, p)| *p == "Y" && matches!(graph.get_node(idx), Some(Node::Sop(_))))
+        .map(|(idx, _)| idx)?;
+    let Some(Node::Sop(sop)) = graph.get_node(&sop_idx) else {
+        return None;
+    };
+    let out = sop.connections.get("Y")?.first()?;
+    // the SOP output must only go to this OLMC, and not leave the module.
+    if graph.find_port(out).is_some() || graph.find_nodes_on_net(out).len() != 2 {
+        return None;
+    }
+    Some(sop_idx)
+}
+
+/// Product counts of a SOP before and after minimisation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinimiseStat {
+    pub name: String,
+    pub before: usize,
+    pub after: usize,
+}
+
+/// Run the two-level minimiser over every SOP in the graph.
+pub fn minimise_sops(graph: &mut Graph) -> Vec<MinimiseStat> {
+    let mut stats = Vec::new();
+    for node in graph.nodelist.iter_mut() {
+        let Node::Sop(sop) = node else {
+            continue;
+        };
+        let cover = Cover::from_params(&sop.parameters);
+        let min = cover.minimise();
+        let stat = MinimiseStat {
+            name: sop.name.clone().unwrap_or_default(),
+            before: sop.parameters.depth as usize,
+            after: min.depth(),
+        };
+        debug!("Minimised {}: {} -> {}", stat.name, stat.before, stat.after);
+        if min.depth() < stat.before {
+            sop.parameters = min.to_params();
+        }
+        stats.push(stat);
+    }
+    stats
+}
+
+/// Output polarity optimisation.
+///
+/// For every OLMC, compute the complement of the SOP on its `A` input. If the complement has
+/// fewer products, the SOP is replaced with it and the OLMC's XOR polarity is flipped, so the
+/// output is unchanged. Returns the names of the SOPs that were complemented.
+pub fn optimise_polarity(graph: &mut Graph) -> Vec<String> {
+    let mut flipped = Vec::new();
+    for olmc in graph.get_olmc_idx() {
+        let Some(sop_idx) = exclusive_sop_for_olmc(graph, &olmc, "A") else {
+            continue;
+        };
+        let Some(Node::Sop(sop)) = graph.nodelist.get_mut(sop_idx.0) else {
+            continue;
+        };
+        if sop.connections.get("A").is_some_and(|a| a.iter().any(|n| !matches!(n, Net::N(_)))) {
+            // leave SOPs with constant inputs alone.
+            continue;
+        }
+        let cover = Cover::from_params(&sop.parameters);
+        let name = sop.name.clone().unwrap_or_default();
+        let Some(inverse) = cover.complement() else {
+            debug!("Polarity check for {name}: the complement is too big");
+            continue;
+        };
+        debug!(
+            "Polarity check for {name}: {} products, complement has {}",
+            cover.depth(),
+            inverse.depth()
+        );
+        if inverse.depth() >= cover.depth() {
+            continue;
+        }
+        info!(
+            "Inverting {name}: {} -> {} products",
+            cover.depth(),
+            inverse.depth()
+        );
+        sop.parameters = inverse.to_params();
+        if let Some(Node::Olmc(o)) = graph.nodelist.get_mut(olmc.0) {
+            o.parameters.inverted = !o.parameters.inverted;
+        }
+        flipped.push(name);
+    }
+    flipped
+}
+
+/// Make registers power up with the values from their `init` attributes.
+///
+/// A register whose init value differs from the chip's power-up level has its `A` SOP
+/// complemented and its polarity flipped, which changes the power-up level without changing
+/// what it loads. Where the flip would not help, or the SOP is shared, this is an error.
+/// Returns the names of the flipped registers.
+pub fn honour_inits(graph: &mut Graph, chip: Chip) -> Result<Vec<String>, MappingError> {
+    let mut flipped = Vec::new();
+    for olmc in graph.get_olmc_idx() {
+        let Some(Node::Olmc(o)) = graph.get_node(&olmc) else {
+            continue;
+        };
+        let Some(y) = o.connections.get("Y").and_then(|y| y.first()) else {
+            continue;
+        };
+        let Some(&init) = graph.inits.get(y) else {
+            continue;
+        };
+        let name = graph.net_name(y);
+        if !o.parameters.registered {
+            warn!("Ignoring the init value on {name}, which is not a register");
+            continue;
+        }
+        let inverted = o.parameters.inverted;
+        if power_up_level(chip, inverted) == init {
+            continue;
+        }
+        let error = MappingError::InitValue {
+            name: name.clone(),
+            init: u8::from(init),
+        };
+        if power_up_level(chip, !inverted) != init {
+            return Err(error);
+        }
+        let Some(sop_idx) = exclusive_sop_for_olmc(graph, &olmc, "A") else {
+            return Err(error);
+        };
+        let Some(Node::Sop(sop)) = graph.nodelist.get_mut(sop_idx.0) else {
+            return Err(error);
+        };
+        let Some(inverse) = Cover::from_params(&sop.parameters).complement() else {
+            return Err(error);
+        };
+        sop.parameters = inverse.to_params();
+        if let Some(Node::Olmc(o)) = graph.nodelist.get_mut(olmc.0) {
+            o.parameters.inverted = !inverted;
+        }
+        info!("Flipped the polarity of {name} so it powers up {}", u8::from(init));
+        flipped.push(name);
+    }
+    Ok(flipped)
+}
+
+/// Substitute `y = f(inputs)` into `sop`, which reads `y` on its `A` input.
+/// Returns the new input list and cover, minimised, or `None` if `!f` is too big to work out.
+fn substitute(sop: &GalSop, y: &Net, inputs: &[Net], f: &Cover) -> Option<(Vec<Net>, Cover)> {
+    let old_inputs = sop.connections.get("A").cloned().unwrap_or_default();
+    let mut new_inputs: Vec<Net> = old_inputs.iter().filter(|n| *n != y).cloned().collect();
+    for n in inputs {
+        if !new_inputs.contains(n) {
+            new_inputs.push(n.clone());
+        }
+    }
+    let width = new_inputs.len();
+    let pos = |n: &Net| new_inputs.iter().position(|x| x == n).unwrap();
+    let not_f = f.complement()?;
+
+    let mut res = Cover::empty(width);
+    for cube in Cover::from_params(&sop.parameters).cubes {
+        let mut base = vec![Literal::DontCare; width];
+        let mut y_lit = Literal::DontCare;
+        for (lit, net) in cube.iter().zip(&old_inputs) {
+            if net == y {
+                y_lit = *lit;
+            } else {
+                base[pos(net)] = *lit;
+            }
+        }
+        let expansion = match y_lit {
+            Literal::DontCare => {
+                res.cubes.push(base);
+                continue;
+            }
+            Literal::One => f,
+            Literal::Zero => &not_f,
+        };
+        for fc in &expansion.cubes {
+            let mut mapped = vec![Literal::DontCare; width];
+            for (lit, net) in fc.iter().zip(inputs) {
+                mapped[pos(net)] = *lit;
+            }
+            if let Some(c) = cube_and(&base, &mapped) {
+                res.cubes.push(c);
+            }
+        }
+    }
+    Some((new_inputs, res.minimise()))
+}
+
+/// Work out how many products the consumer SOP can have, given the row its OLMC would use.
+fn consumer_capacity(graph: &Graph, pcf: &PcfFile, chip: Chip, sop: &GalSop) -> usize {
+    let min_row = (0..chip.num_olmcs())
+        .map(|r| row_capacity(chip, r, false))
+        .min()
+        .unwrap_or(0);
+    let Some(out) = sop.connections.get("Y").and_then(|y| y.first()) else {
+        return 0;
+    };
+    let olmc = graph.find_nodes_on_net(out).into_iter().find_map(|idx| {
+        match graph.get_node(&idx) {
+            Some(Node::Olmc(o)) => Some(o),
+            _ => None,
+        }
+    });
+    let Some(olmc) = olmc else {
+        return 0;
+    };
+    if olmc.connections.get("E").is_some_and(|e| e.contains(out)) {
+        // output enables only get a single product.
+        return 1;
+    }
+    // a constrained OLMC has a known row, otherwise assume the smallest one.
+    olmc.connections
+        .get("Y")
+        .and_then(|y| y.first())
+        .and_then(|y| graph.find_port(y))
+        .and_then(|port| port.lookup(pcf))
+        .and_then(|pin| chip.pin_to_olmc(pin as usize))
+        .map_or(min_row, |row| {
+            row_capacity(chip, row, olmc.parameters.registered)
+        })
+}
+
+/// Collapse buried combinational OLMCs back into the SOPs that read them.
+///
+/// `synth_gal.tcl` puts an OLMC between chained SOPs, which costs a pin and a feedback delay.
+/// When every consumer of a buried OLMC still fits its row after substituting the OLMC's SOP,
+/// the consumers are flattened and the OLMC and its SOP are removed. Returns the names of the
+/// removed OLMCs.
+pub fn collapse_buried(graph: &mut Graph, pcf: &PcfFile, chip: Chip) -> Vec<String> {
+    let mut collapsed = Vec::new();
+    'restart: loop {
+        for olmc_idx in graph.get_olmc_idx() {
+            let Some(Node::Olmc(olmc)) = graph.get_node(&olmc_idx) else {
+                continue;
+            };
+            let Some(y) = olmc.connections.get("Y").and_then(|y| y.first()) else {
+                continue;
+            };
+            let buried = !olmc.parameters.registered
+                && olmc.connections.get("E") == Some(&vec![Net::LiteralOne])
+                && graph.find_port(y).is_none();
+            if !buried {
+                continue;
+            }
+            let Some(sop_idx) = exclusive_sop_for_olmc(graph, &olmc_idx, "A") else {
+                continue;
+            };
+            let Some(Node::Sop(sop)) = graph.get_node(&sop_idx) else {
+                continue;
+            };
+            let inputs = sop.connections.get("A").cloned().unwrap_or_default();
+            let mut f = Cover::from_params(&sop.parameters);
+            if olmc.parameters.inverted {
+                let Some(inverse) = f.complement() else {
+                    continue;
+                };
+                f = inverse;
+            }
+
+            // every other node on the output must be a SOP reading it on A.
+            let consumers: Vec<NodeIdx> = graph
+                .find_nodes_on_net(y)
+                .into_iter()
+                .filter(|idx| *idx != olmc_idx)
+                .collect();
+            let mut rewritten = Vec::new();
+            for idx in &consumers {
+                let Some(Node::Sop(consumer)) = graph.get_node(idx) else {
+                    break;
+                };
+                if !consumer.connections.get("A").is_some_and(|a| a.contains(y)) {
+                    break;
+                }
+                let Some((new_inputs, cover)) = substitute(consumer, y, &inputs, &f) else {
+                    break;
+                };
+                if cover.depth() > consumer_capacity(graph, pcf, chip, consumer) {
+                    break;
+                }
+                rewritten.push((*idx, new_inputs, cover));
+            }
+            if consumers.is_empty() || rewritten.len() != consumers.len() {
+                continue;
+            }
+
+            let name = olmc.name.clone().unwrap_or_default();
+            info!("Collapsing buried OLMC {name} into {} SOPs", rewritten.len());
+            for (idx, new_inputs, cover) in rewritten {
+                if let Some(Node::Sop(consumer)) = graph.nodelist.get_mut(idx.0) {
+                    debug!(
+                        "{:?}: {} -> {} products",
+                        consumer.name,
+                        consumer.parameters.depth,
+                        cover.depth()
+                    );
+                    consumer.connections.insert("A".to_string(), new_inputs);
+                    consumer.parameters = cover.to_params();
+                }
+            }
+            let (first, second) = if olmc_idx.0 > sop_idx.0 {
+                (olmc_idx, sop_idx)
+            } else {
+                (sop_idx, olmc_idx)
+            };
+            graph.nodelist.remove(first.0);
+            graph.nodelist.remove(second.0);
+            graph.generate_adjacency();
+            collapsed.push(name);
+            continue 'restart;
+        }
+        break;
+    }
+    collapsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, inverted, named, ports, sop};
+    use crate::yosys_parser::PortDirection;
+
+    #[test]
+    fn test_minimise_sops() {
+        let mut g = Graph::default();
+        g.nodelist.push(named(sop(&[2, 3], 4, "10101001"), "top/sop"));
+        let stats = minimise_sops(&mut g);
+        assert_eq!(
+            stats,
+            vec![MinimiseStat {
+                name: "top/sop".to_string(),
+                before: 2,
+                after: 1
+            }]
+        );
+        let Node::Sop(sop) = &g.nodelist[0] else {
+            panic!("expected sop");
+        };
+        assert_eq!(sop.parameters.table, "1000");
+    }
+
+    fn olmc(name: &str, a: u32, y: u32, inverted: bool) -> Node {
+        reg(name, a, y, inverted, false)
+    }
+
+    fn reg(name: &str, a: u32, y: u32, invert: bool, registered: bool) -> Node {
+        let olmc = named(test_util::olmc(a, Net::LiteralOne, y, registered), name);
+        if invert {
+            inverted(olmc)
+        } else {
+            olmc
+        }
+    }
+
+    #[test]
+    fn test_collapse_buried() {
+        let mut g = Graph::default();
+        // y = !(a & b), out = y & c
+        g.nodelist.push(named(sop(&[2, 3], 10, "1010"), "top/inner"));
+        g.nodelist.push(olmc("top/buried", 10, 11, true));
+        g.nodelist.push(named(sop(&[11, 4], 12, "1010"), "top/outer"));
+        g.nodelist.push(olmc("top/out", 12, 13, false));
+        ports(&mut g, &[("out", 13, PortDirection::Output)]);
+        g.generate_adjacency();
+
+        let removed = collapse_buried(&mut g, &PcfFile::default(), Chip::GAL16V8);
+        assert_eq!(removed, vec!["top/buried".to_string()]);
+        assert_eq!(g.nodelist.len(), 2);
+        let Node::Sop(sop) = &g.nodelist[0] else {
+            panic!("expected sop");
+        };
+        let inputs = sop.connections.get("A").unwrap().clone();
+        assert_eq!(inputs, vec![Net::N(4), Net::N(2), Net::N(3)]);
+        // c & !a + c & !b
+        let cover = Cover::from_params(&sop.parameters);
+        assert_eq!(cover.depth(), 2);
+        for n in 0..8 {
+            let i: Vec<bool> = (0..3).map(|b| n & (1 << b) != 0).collect();
+            assert_eq!(cover.eval(&i), i[0] && !(i[1] && i[2]));
+        }
+    }
+
+    #[test]
+    fn test_wide_or_is_inverted() {
+        let mut g = Graph::default();
+        g.nodelist.push(named(sop(&[2, 3, 4], 5, "000010001000100000"), "top/or"));
+        g.nodelist.push(olmc("top/olmc", 5, 6, false));
+        g.generate_adjacency();
+
+        assert_eq!(optimise_polarity(&mut g), vec!["top/or".to_string()]);
+        let Node::Sop(sop) = &g.nodelist[0] else {
+            panic!("expected sop");
+        };
+        assert_eq!(sop.parameters.depth, 1);
+        assert_eq!(sop.parameters.table, "010101");
+        let Node::Olmc(olmc) = &g.nodelist[1] else {
+            panic!("expected olmc");
+        };
+        assert!(olmc.parameters.inverted);
+    }
+
+    #[test]
+    fn test_honour_inits() {
+        let mut g = Graph::default();
+        // the register loads a & b and the inverted output puts !(a & b) on q, which is
+        // initialised to 0.
+        g.nodelist.push(named(sop(&[2, 3], 4, "1010"), "top/and"));
+        g.nodelist.push(reg("top/q", 4, 5, true, true));
+        g.netnames.insert(Net::N(5), "q".to_string());
+        g.inits.insert(Net::N(5), false);
+        g.generate_adjacency();
+
+        // the 16V8 always powers up high.
+        let mut g16 = g.clone();
+        assert!(matches!(
+            honour_inits(&mut g16, Chip::GAL16V8),
+            Err(MappingError::InitValue { name, init: 0 }) if name == "q"
+        ));
+
+        // the 22V10 can power up low once the register is no longer inverted.
+        assert_eq!(honour_inits(&mut g, Chip::GAL22V10).unwrap(), vec!["q"]);
+        let Node::Olmc(olmc) = &g.nodelist[1] else {
+            panic!("expected olmc");
+        };
+        assert!(!olmc.parameters.inverted);
+        let Node::Sop(sop) = &g.nodelist[0] else {
+            panic!("expected sop");
+        };
+        // after the flip the register loads the complemented SOP, !a | !b, rather than a & b;
+        // only the pin output, !(a & b), is unchanged.
+        let cover = Cover::from_params(&sop.parameters);
+        assert_eq!(cover.depth(), 2);
+        for (a, b) in [(false, false), (false, true), (true, false), (true, true)] {
+            assert_eq!(cover.eval(&[a, b]), !(a && b));
+        }
+        assert!(honour_inits(&mut g, Chip::GAL22V10).unwrap().is_empty());
+    }
+}
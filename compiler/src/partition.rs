@@ -0,0 +1,411 @@
+//! Partitioning a design across several chips of the same type.
+//!
+//! Every OLMC, together with the SOPs driving its `A` and `E` inputs, forms a unit that is
+//! placed on exactly one chip. OLMCs sharing a SOP go in the same unit, so each SOP ends up
+//! on a single chip. Nets crossing between chips become an output port on the
+//! driving chip and an input port on each chip that reads them.
+use crate::fitter::{dedicated_inputs, MappingError};
+use crate::yosys_parser::{GalInput, Graph, NamedPort, Net, Node, NodeIdx, PortDirection};
+use galette::chips::Chip;
+use log::{debug, info};
+use std::collections::{BTreeSet, HashMap};
+
+/// A net that is driven on one chip and read on others.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CutNet {
+    /// The port name used for the net on every chip.
+    pub name: String,
+    pub from: usize,
+    pub to: Vec<usize>,
+}
+
+/// A design split across several chips.
+#[derive(Debug)]
+pub struct Partition {
+    /// One graph per chip, with cut nets turned into ports.
+    pub devices: Vec<Graph>,
+    pub cut: Vec<CutNet>,
+}
+
+/// A group of OLMCs and the SOPs that drive them.
+struct Unit {
+    nodes: Vec<NodeIdx>,
+    /// Nets read by the unit's SOPs. Clock nets are kept separately.
+    inputs: BTreeSet<Net>,
+    clocks: BTreeSet<Net>,
+    /// One output net per OLMC.
+    outputs: Vec<Net>,
+}
+
+impl Unit {
+    fn reads(&self, other: &Unit) -> bool {
+        other.outputs.iter().any(|o| self.inputs.contains(o))
+    }
+}
+
+fn find_units(graph: &Graph) -> Vec<Unit> {
+    let mut units: Vec<Unit> = Vec::new();
+    for olmc_idx in graph.get_olmc_idx() {
+        let Some(Node::Olmc(olmc)) = graph.get_node(&olmc_idx) else {
+            continue;
+        };
+        let mut nodes = vec![olmc_idx];
+        let mut inputs = BTreeSet::new();
+        for port in ["A", "E"] {
+            for adj in graph.get_node_port_conns(&olmc_idx, port) {
+                let Some((idx, "Y")) = adj.get_other(&olmc_idx, port) else {
+                    continue;
+                };
+                if let Some(Node::Sop(sop)) = graph.get_node(&idx) {
+                    nodes.push(idx);
+                    inputs.extend(sop.connections.get("A").into_iter().flatten().cloned());
+                }
+            }
+        }
+        let clocks = olmc.connections.get("C").into_iter().flatten().cloned().collect();
+        let output = olmc.connections.get("Y").and_then(|y| y.first()).cloned();
+        let Some(output) = output else {
+            continue;
+        };
+        inputs.retain(|n| matches!(n, Net::N(_)));
+        let mut unit = Unit {
+            nodes,
+            inputs,
+            clocks,
+            outputs: vec![output],
+        };
+        // fold in every unit that already holds one of our SOPs.
+        while let Some(i) = units
+            .iter()
+            .position(|u| u.nodes.iter().any(|n| unit.nodes[1..].contains(n)))
+        {
+            let other = units.remove(i);
+            for n in other.nodes {
+                if !unit.nodes.contains(&n) {
+                    unit.nodes.push(n);
+                }
+            }
+            unit.inputs.extend(other.inputs);
+            unit.clocks.extend(other.clocks);
+            unit.outputs.extend(other.outputs);
+        }
+        units.push(unit);
+    }
+    units
+}
+
+/// Number of inter-chip connections for an assignment of units to chips.
+fn cut_cost(units: &[Unit], assign: &[usize]) -> usize {
+    units
+        .iter()
+        .enumerate()
+        .map(|(i, u)| {
+            u.outputs
+                .iter()
+                .map(|out| {
+                    let readers: BTreeSet<usize> = units
+                        .iter()
+                        .enumerate()
+                        .filter(|(j, other)| assign[*j] != assign[i] && other.inputs.contains(out))
+                        .map(|(j, _)| assign[j])
+                        .collect();
+                    readers.len()
+                })
+                .sum::<usize>()
+        })
+        .sum()
+}
+
+/// Check the units assigned to `device` fit into its OLMCs and input pins.
+fn feasible(units: &[Unit], assign: &[usize], device: usize, chip: Chip) -> bool {
+    let members: Vec<&Unit> = units
+        .iter()
+        .zip(assign)
+        .filter(|(_, d)| **d == device)
+        .map(|(u, _)| u)
+        .collect();
+    let olmcs: usize = members.iter().map(|u| u.outputs.len()).sum();
+    if olmcs > chip.num_olmcs() {
+        return false;
+    }
+    let produced: BTreeSet<&Net> = members.iter().flat_map(|u| &u.outputs).collect();
+    let inputs: BTreeSet<&Net> = members
+        .iter()
+        .flat_map(|u| &u.inputs)
+        .filter(|n| !produced.contains(n))
+        .collect();
+    let clocked = members.iter().any(|u| u.clocks.iter().any(|n| matches!(n, Net::N(_))));
+    // inputs can spill onto OLMC pins that aren't used by a unit.
+    let pins = dedicated_inputs(chip, clocked).len() + chip.num_olmcs() - olmcs;
+    inputs.len() <= pins
+}
+
+/// Assign units to chips: a greedy pass that keeps connected units together, followed by
+/// single-unit moves that reduce the number of cut nets.
+fn assign_units(units: &[Unit], devices: usize, chip: Chip) -> Option<Vec<usize>> {
+    const UNASSIGNED: usize = usize::MAX;
+    let mut assign = vec![UNASSIGNED; units.len()];
+    // place the most connected units first.
+    let mut order: Vec<usize> = (0..units.len()).collect();
+    order.sort_by_key(|i| std::cmp::Reverse(units[*i].inputs.len()));
+    for i in order {
+        let mut best: Option<(usize, (usize, std::cmp::Reverse<usize>))> = None;
+        for d in 0..devices {
+            assign[i] = d;
+            if !feasible(units, &assign, d, chip) {
+                continue;
+            }
+            let shared = units
+                .iter()
+                .enumerate()
+                .filter(|(j, u)| {
+                    assign[*j] == d
+                        && *j != i
+                        && (u.reads(&units[i])
+                            || units[i].reads(u)
+                            || !u.inputs.is_disjoint(&units[i].inputs))
+                })
+                .count();
+            let load = assign.iter().filter(|x| **x == d).count();
+            let score = (shared, std::cmp::Reverse(load));
+            if best.is_none_or(|(_, s)| score > s) {
+                best = Some((d, score));
+            }
+        }
+        assign[i] = best?.0;
+    }
+
+    let mut cost = cut_cost(units, &assign);
+    loop {
+        let mut improved = false;
+        for i in 0..units.len() {
+            let from = assign[i];
+            for d in (0..devices).filter(|d| *d != from) {
+                assign[i] = d;
+                let c = cut_cost(units, &assign);
+                if c < cost && feasible(units, &assign, d, chip) {
+                    cost = c;
+                    improved = true;
+                    break;
+                }
+                assign[i] = from;
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+    debug!("Partition assignment {:?} with {cost} cut nets", assign);
+    Some(assign)
+}
+
+/// Split the graph into `devices` graphs, each of which should fit on `chip`.
+pub fn partition(graph: &Graph, chip: Chip, devices: usize) -> Result<Partition, MappingError> {
+    let units = find_units(graph);
+    let assign =
+        assign_units(&units, devices, chip).ok_or(MappingError::PartitionFailed(devices))?;
+
+    // GAL_INPUTs by the net they drive, so each chip can get a copy of the ones it reads.
+    let mut input_cells: HashMap<Net, NodeIdx> = HashMap::new();
+    for (idx, node) in graph.nodelist.iter().enumerate() {
+        if let Node::Input(i) = node {
+            for n in i.connections.get("Y").into_iter().flatten() {
+                input_cells.insert(n.clone(), NodeIdx(idx));
+            }
+        }
+    }
+    let max_net = graph
+        .nodelist
+        .iter()
+        .flat_map(|n| n.get_nets())
+        .chain(graph.ports.iter().map(|p| p.net.clone()))
+        .filter_map(|n| match n {
+            Net::N(x) => Some(x),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+
+    // work out the cut nets and their names first, since both sides need the same port name.
+    let mut cut = Vec::new();
+    for (i, u) in units.iter().enumerate() {
+        for output in &u.outputs {
+            let to: BTreeSet<usize> = units
+                .iter()
+                .enumerate()
+                .filter(|(j, other)| assign[*j] != assign[i] && other.inputs.contains(output))
+                .map(|(j, _)| assign[j])
+                .collect();
+            if to.is_empty() {
+                continue;
+            }
+            let name = match (output, graph.find_port(output)) {
+                (_, Some(port)) => port.name.clone(),
+                (Net::N(n), None) => format!("cut_{n}"),
+                (n, None) => format!("cut_{n:?}"),
+            };
+            cut.push((output.clone(), CutNet {
+                name,
+                from: assign[i],
+                to: to.into_iter().collect(),
+            }));
+        }
+    }
+
+    let mut next_net = max_net + 1;
+    let mut result = Vec::new();
+    for d in 0..devices {
+        let mut g = Graph::default();
+        let members: Vec<&Unit> = units
+            .iter()
+            .zip(&assign)
+            .filter(|(_, a)| **a == d)
+            .map(|(u, _)| u)
+            .collect();
+        for u in &members {
+            for idx in &u.nodes {
+                g.nodelist.push(graph.get_node(idx).unwrap().clone());
+            }
+            for output in &u.outputs {
+                if let Some(port) = graph.find_port(output) {
+                    g.ports.push(port.clone());
+                }
+            }
+        }
+        let produced: BTreeSet<&Net> = members.iter().flat_map(|u| &u.outputs).collect();
+        let read: BTreeSet<&Net> = members
+            .iter()
+            .flat_map(|u| u.inputs.iter().chain(&u.clocks))
+            .filter(|n| matches!(n, Net::N(_)) && !produced.contains(n))
+            .collect();
+        for net in read {
+            if let Some(idx) = input_cells.get(net) {
+                let node = graph.get_node(idx).unwrap().clone();
+                let port = node
+                    .get_connections()
+                    .get("A")
+                    .and_then(|a| a.first())
+                    .and_then(|a| graph.find_port(a))
+                    .cloned();
+                g.nodelist.push(node);
+                g.ports.extend(port);
+            } else if let Some((_, c)) = cut.iter().find(|(n, _)| n == net) {
+                // a fresh net for the pin, buffered onto the original net by a GAL_INPUT.
+                let pin_net = Net::N(next_net);
+                next_net += 1;
+                g.ports.push(NamedPort {
+                    name: c.name.clone(),
+                    net: pin_net.clone(),
+                    direction: PortDirection::Input,
+                });
+                g.nodelist.push(Node::Input(GalInput {
+                    name: Some(format!("partition/{}", c.name)),
+                    connections: HashMap::from([
+                        ("A".to_string(), vec![pin_net]),
+                        ("Y".to_string(), vec![net.clone()]),
+                    ]),
+                }));
+            }
+        }
+        // cut nets driven here need an output port, unless they already have one.
+        for (net, c) in &cut {
+            if c.from == d && !g.ports.iter().any(|p| &p.net == net) {
+                g.ports.push(NamedPort {
+                    name: c.name.clone(),
+                    net: net.clone(),
+                    direction: PortDirection::Output,
+                });
+            }
+        }
+        g.netnames = graph.netnames.clone();
+        g.inits = graph.inits.clone();
+        g.upto = graph.upto.clone();
+        g.one_sops = graph.one_sops.clone();
+        g.generate_adjacency();
+        info!(
+            "Chip {d}: {} OLMCs, {} ports",
+            members.iter().map(|u| u.outputs.len()).sum::<usize>(),
+            g.ports.len()
+        );
+        result.push(g);
+    }
+
+    Ok(Partition {
+        devices: result,
+        cut: cut.into_iter().map(|(_, c)| c).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{input, named, olmc, ports, sop};
+
+    /// A chain of `n` buffers from port `in` through OLMCs, each one visible as `out[i]`.
+    fn chain(n: u32) -> Graph {
+        let mut g = Graph::default();
+        ports(&mut g, &[("in", 2, PortDirection::Input)]);
+        g.nodelist.push(named(input(2, 3), "top/in"));
+        let mut prev = 3;
+        for i in 0..n {
+            let (sop_out, out) = (100 + 2 * i, 101 + 2 * i);
+            g.nodelist.push(named(sop(&[prev], sop_out, "10"), &format!("top/sop{i}")));
+            let buffer = olmc(sop_out, Net::LiteralOne, out, false);
+            g.nodelist.push(named(buffer, &format!("top/olmc{i}")));
+            ports(&mut g, &[(&format!("out[{i}]"), out, PortDirection::Output)]);
+            prev = out;
+        }
+        g.generate_adjacency();
+        g
+    }
+
+    #[test]
+    fn test_partition_chain() -> Result<(), MappingError> {
+        let g = chain(12);
+        assert!(partition(&g, Chip::GAL16V8, 1).is_err());
+
+        let p = partition(&g, Chip::GAL16V8, 2)?;
+        assert_eq!(p.devices.len(), 2);
+        for d in &p.devices {
+            assert!(d.get_olmc_idx().len() <= 8);
+        }
+        // a chain split in two only needs a single connection.
+        assert_eq!(p.cut.len(), 1);
+        let c = &p.cut[0];
+        assert_ne!(c.from, c.to[0]);
+        assert!(p.devices[c.from]
+            .ports
+            .iter()
+            .any(|port| port.name == c.name && port.direction == PortDirection::Output));
+        assert!(p.devices[c.to[0]]
+            .ports
+            .iter()
+            .any(|port| port.name == c.name && port.direction == PortDirection::Input));
+        Ok(())
+    }
+
+    #[test]
+    fn test_partition_shared_sop() -> Result<(), MappingError> {
+        // one SOP feeding two OLMCs.
+        let mut g = Graph::default();
+        ports(&mut g, &[("in", 2, PortDirection::Input)]);
+        g.nodelist.push(named(input(2, 3), "top/in"));
+        g.nodelist.push(named(sop(&[3], 4, "10"), "top/sop"));
+        for i in 0..2 {
+            let buffer = olmc(4, Net::LiteralOne, 10 + i, false);
+            g.nodelist.push(named(buffer, &format!("top/olmc{i}")));
+            ports(&mut g, &[(&format!("out[{i}]"), 10 + i, PortDirection::Output)]);
+        }
+        g.generate_adjacency();
+
+        let p = partition(&g, Chip::GAL16V8, 2)?;
+        let sops: usize = p
+            .devices
+            .iter()
+            .map(|d| d.nodelist.iter().filter(|n| matches!(n, Node::Sop(_))).count())
+            .sum();
+        assert_eq!(sops, 1);
+        assert!(p.cut.is_empty());
+        Ok(())
+    }
+}
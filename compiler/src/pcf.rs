@@ -1,6 +1,8 @@
 use regex::Regex;
 use std::collections::HashMap;
+use std::fmt;
 
+#[derive(Debug, Default, Clone)]
 pub struct PcfFile {
     map: HashMap<String, u32>,
 }
@@ -28,6 +30,32 @@ impl PcfFile {
         let realname = format!("{name}[{index}]");
         self.map.get(&realname).cloned()
     }
+    /// The ports constrained to a pin, in name order.
+    pub fn ports_on(&self, pin: u32) -> Vec<&str> {
+        let mut ports: Vec<&str> = self
+            .map
+            .iter()
+            .filter(|(_, p)| **p == pin)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        ports.sort();
+        ports
+    }
+    /// Constrain a port to a pin, returning the previous pin if there was one.
+    pub fn insert(&mut self, name: &str, pin: u32) -> Option<u32> {
+        self.map.insert(name.to_string(), pin)
+    }
+}
+
+impl fmt::Display for PcfFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut pins: Vec<_> = self.map.iter().collect();
+        pins.sort_by_key(|(name, pin)| (**pin, name.as_str()));
+        for (name, pin) in pins {
+            writeln!(f, "set_io {name} {pin}")?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -56,6 +84,15 @@ set_io vec[1] 3";
         assert_eq!(f.pinvec("vec", 2), None);
     }
 
+    #[test]
+    fn test_write() {
+        let mut f = PcfFile::default();
+        f.insert("b", 3);
+        f.insert("a", 2);
+        assert_eq!(f.to_string(), "set_io a 2\nset_io b 3\n");
+        assert_eq!(parse_pcf(&f.to_string()).pin("b"), Some(3));
+    }
+
     #[test]
     #[should_panic]
     fn test_name_collision() {
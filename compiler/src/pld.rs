@@ -0,0 +1,143 @@
+//! Write a fitted design as galette/galasm `.pld` source.
+//!
+//! The output has the chip line, signature, a pin list using the design's own signal names,
+//! and one equation per OLMC, with `.R`, `.T` and `.E` suffixes as programmed in the blueprint.
+
+use crate::fitter::{chip_to_olmc_offset, Fit};
+use crate::terms::{self, term_expr, Syntax};
+use crate::yosys_parser::Graph;
+use galette::blueprint::{Active, PinMode};
+use galette::chips::Chip;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Make a name galette will accept: letters, digits and underscores only.
+fn pld_name(name: &str) -> String {
+    let mut out: String = name
+        .chars()
+        .filter_map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => Some(c),
+            '[' | '.' | '/' | '$' | ':' => Some('_'),
+            _ => None,
+        })
+        .collect::<String>()
+        .trim_matches('_')
+        .to_string();
+    if !out.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        out.insert(0, 'S');
+    }
+    out
+}
+
+/// Sums of products, one product per line.
+const SYNTAX: Syntax = Syntax {
+    zero: "GND",
+    one: "VCC",
+    unknown: "NC",
+    not: "/",
+    and: " * ",
+    or: "\n    + ",
+    bracket: false,
+};
+
+/// Pin names for the pin list, with unused pins as `NC` and the supply pins named.
+fn pin_names(fit: &Fit, graph: &Graph) -> BTreeMap<u32, String> {
+    let num_pins = fit.chip.num_pins() as u32;
+    let mut names = terms::pin_names(fit, graph, pld_name, &["NC", "GND", "VCC"], false);
+    names.insert(num_pins / 2, "GND".to_string());
+    names.insert(num_pins, "VCC".to_string());
+    if fit.chip == Chip::GAL16V8 {
+        // registered mode reserves these.
+        names.entry(1).or_insert("CLK".to_string());
+        names.entry(11).or_insert("OE".to_string());
+    }
+    for pin in 1..=num_pins {
+        names.entry(pin).or_insert("NC".to_string());
+    }
+    names
+}
+
+/// Generate PLD source equivalent to the fitted design.
+pub fn to_pld(fit: &Fit, graph: &Graph) -> String {
+    let chip = fit.chip;
+    let names = pin_names(fit, graph);
+    let literals = terms::plain(&names);
+    let module = graph.module_name();
+
+    let mut out = String::new();
+    writeln!(out, "{}", chip.name()).unwrap();
+    let sig = if fit.blueprint.sig.is_empty() {
+        pld_name(&module).chars().take(8).collect()
+    } else {
+        String::from_utf8_lossy(&fit.blueprint.sig).to_string()
+    };
+    writeln!(out, "{sig}").unwrap();
+    writeln!(out).unwrap();
+    let pins: Vec<&str> = names.values().map(|n| n.as_str()).collect();
+    let (first, second) = pins.split_at(pins.len() / 2);
+    writeln!(out, "{}", first.join(" ")).unwrap();
+    writeln!(out, "{}", second.join(" ")).unwrap();
+
+    let offset = chip_to_olmc_offset(&chip);
+    for (row, olmc) in fit.blueprint.olmcs.iter().enumerate() {
+        let Some((mode, term)) = &olmc.output else {
+            continue;
+        };
+        let pin = (row + offset) as u32;
+        let name = &names[&pin];
+        let lhs = if olmc.active == Active::Low {
+            format!("/{name}")
+        } else {
+            name.clone()
+        };
+        let suffix = match mode {
+            PinMode::Registered => ".R",
+            PinMode::Tristate => ".T",
+            PinMode::Combinatorial => "",
+        };
+        writeln!(out).unwrap();
+        writeln!(out, "{lhs}{suffix} = {}", term_expr(term, &literals, &SYNTAX)).unwrap();
+        if let Some(en) = &olmc.tri_con {
+            writeln!(out, "{name}.E = {}", term_expr(en, &literals, &SYNTAX)).unwrap();
+        } else if *mode == PinMode::Tristate {
+            writeln!(out, "{name}.E = VCC").unwrap();
+        }
+    }
+    if let Some(ar) = &fit.blueprint.ar {
+        writeln!(out, "\nAR = {}", term_expr(ar, &literals, &SYNTAX)).unwrap();
+    }
+    if let Some(sp) = &fit.blueprint.sp {
+        writeln!(out, "\nSP = {}", term_expr(sp, &literals, &SYNTAX)).unwrap();
+    }
+
+    writeln!(out).unwrap();
+    writeln!(out, "DESCRIPTION").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "Generated by ver2gal from module {module}.").unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::writer_fit;
+
+    #[test]
+    fn test_pld_name() {
+        assert_eq!(pld_name("io[3]"), "io_3");
+        assert_eq!(pld_name("$abc$98$n1"), "abc_98_n1");
+        assert_eq!(pld_name("1st"), "S1st");
+    }
+
+    #[test]
+    fn test_to_pld() {
+        let (fit, g) = writer_fit(Chip::GAL16V8);
+        let pld = to_pld(&fit, &g);
+        let lines: Vec<&str> = pld.lines().collect();
+        assert_eq!(lines[0], "GAL16V8");
+        assert_eq!(lines[3], "clk in_0 in_1 NC NC NC NC NC NC GND");
+        assert_eq!(lines[4], "OE NC NC NC NC NC NC y q VCC");
+        assert!(pld.contains("q.R = in_0 * /in_1\n    + q\n"), "{pld}");
+        assert!(pld.contains("/y.T = in_0 * /in_1\n    + q\ny.E = in_1\n"), "{pld}");
+    }
+}
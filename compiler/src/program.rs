@@ -0,0 +1,224 @@
+//! Program a chip through an external programmer CLI, then read it back and verify it.
+//!
+//! The programmer is driven by two command templates, one to write a JEDEC file and one to
+//! read the chip back into a file. Templates are split on whitespace and run directly, not
+//! through a shell, with `{device}`, `{jed}` and `{readback}` replaced in each argument.
+
+use crate::device::Device;
+use crate::jedec::{self, JedecError};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ProgramError {
+    #[error("Empty programmer command template")]
+    EmptyTemplate,
+
+    #[error("Could not run {cmd}: {source}")]
+    Spawn { cmd: String, source: io::Error },
+
+    #[error("{cmd} failed with {status}")]
+    CommandFailed { cmd: String, status: String },
+
+    #[error("Could not read {path}: {source}")]
+    Read { path: String, source: io::Error },
+
+    #[error("{path}: {source}")]
+    Jedec { path: String, source: JedecError },
+
+    #[error("{path} has {found} fuses, but the {device} has {expected}")]
+    FuseCount {
+        path: String,
+        device: String,
+        found: usize,
+        expected: usize,
+    },
+}
+
+/// Command templates for a programmer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Programmer {
+    pub write: String,
+    pub read: String,
+}
+
+impl Programmer {
+    pub fn minipro() -> Self {
+        Programmer {
+            write: "minipro -p {device} -w {jed}".to_string(),
+            read: "minipro -p {device} -r {readback}".to_string(),
+        }
+    }
+}
+
+/// Split a template into a program and its arguments, filling in the placeholders.
+pub fn expand(template: &str, vars: &[(&str, &str)]) -> Vec<String> {
+    template
+        .split_whitespace()
+        .map(|arg| {
+            vars.iter().fold(arg.to_string(), |arg, (name, value)| {
+                arg.replace(&format!("{{{name}}}"), value)
+            })
+        })
+        .collect()
+}
+
+fn run(template: &str, vars: &[(&str, &str)]) -> Result<(), ProgramError> {
+    let args = expand(template, vars);
+    let (program, rest) = args.split_first().ok_or(ProgramError::EmptyTemplate)?;
+    let cmd = args.join(" ");
+    let status = Command::new(program)
+        .args(rest)
+        .status()
+        .map_err(|source| ProgramError::Spawn {
+            cmd: cmd.clone(),
+            source,
+        })?;
+    if !status.success() {
+        return Err(ProgramError::CommandFailed {
+            cmd,
+            status: status.to_string(),
+        });
+    }
+    Ok(())
+}
+
+fn read_jedec(path: &Path, readback: bool) -> Result<jedec::Jedec, ProgramError> {
+    let name = path.display().to_string();
+    let text = fs::read_to_string(path).map_err(|source| ProgramError::Read {
+        path: name.clone(),
+        source,
+    })?;
+    let text = if readback {
+        jedec::normalise_readback(&text)
+    } else {
+        text
+    };
+    jedec::parse(&text).map_err(|source| ProgramError::Jedec { path: name, source })
+}
+
+fn check_fuse_count(
+    path: &Path,
+    jed: &jedec::Jedec,
+    device: &Device,
+    expected: usize,
+) -> Result<(), ProgramError> {
+    if jed.fuse_count != expected {
+        return Err(ProgramError::FuseCount {
+            path: path.display().to_string(),
+            device: device.name.to_string(),
+            found: jed.fuse_count,
+            expected,
+        });
+    }
+    Ok(())
+}
+
+/// Compare a JEDEC file for the device with a readback of it, returning a description of
+/// every fuse that differs.
+pub fn verify(device: &Device, jed: &Path, readback: &Path) -> Result<Vec<String>, ProgramError> {
+    let want = read_jedec(jed, false)?;
+    check_fuse_count(jed, &want, device, device.fuse_count)?;
+    let got = read_jedec(readback, true)?;
+    check_fuse_count(readback, &got, device, device.readback_fuse_count)?;
+    Ok(jedec::compare(device, &want, &got))
+}
+
+/// Write `jed` to the device, read it back into `readback`, and verify it. With `write`
+/// unset the device is only read back and verified. `part` is the name the programmer knows
+/// the device by.
+pub fn program(
+    programmer: &Programmer,
+    part: &str,
+    device: &Device,
+    jed: &Path,
+    readback: &Path,
+    write: bool,
+) -> Result<Vec<String>, ProgramError> {
+    let jed_name = jed.display().to_string();
+    let readback_name = readback.display().to_string();
+    let vars = [
+        ("device", part),
+        ("jed", jed_name.as_str()),
+        ("readback", readback_name.as_str()),
+    ];
+    if write {
+        run(&programmer.write, &vars)?;
+    }
+    run(&programmer.read, &vars)?;
+    verify(device, jed, readback)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let args = expand(
+            "minipro -p {device} -r {readback}",
+            &[("device", "ATF16V8B"), ("readback", "/tmp/rb.jed")],
+        );
+        assert_eq!(args, ["minipro", "-p", "ATF16V8B", "-r", "/tmp/rb.jed"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_program() {
+        let dir = std::env::temp_dir().join(format!("ver2gal_program_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // a stand-in for minipro that stores the written file as the chip, and reads it
+        // back with the broken trailer minipro writes.
+        let chip = dir.join("chip.jed");
+        let script = dir.join("minipro");
+        fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\ncase \"$3\" in\n-w) cp \"$4\" {0} ;;\n\
+                 -r) sed -n '/^C/{{s/\\*$//;p;q;}};p' {0} > \"$4\"; printf '\\0031234' >> \"$4\" ;;\n\
+                 esac\n",
+                chip.display()
+            ),
+        )
+        .unwrap();
+
+        let jed_text = |fuses: &[bool]| {
+            let bits: String = fuses.iter().map(|f| if *f { '1' } else { '0' }).collect();
+            let sum = jedec::fuse_checksum(fuses);
+            format!("\x02\nGAL16V8*\nQF2194*\nL0000 {bits}*\nC{sum:04X}*\n\x030000\n")
+        };
+        let mut fuses = vec![false; 2194];
+        fuses[40] = true;
+        let jed = dir.join("out.jed");
+        fs::write(&jed, jed_text(&fuses)).unwrap();
+
+        let programmer = Programmer {
+            write: format!("sh {} -p {{device}} -w {{jed}}", script.display()),
+            read: format!("sh {} -p {{device}} -r {{readback}}", script.display()),
+        };
+        let readback = dir.join("readback.jed");
+        let atf = crate::device::lookup("ATF16V8B").unwrap();
+        let diffs = program(&programmer, "ATF16V8B", atf, &jed, &readback, true).unwrap();
+        assert!(diffs.is_empty(), "{diffs:?}");
+
+        // a chip that lost a fuse is reported by what the fuse does.
+        fs::write(&chip, jed_text(&vec![false; 2194])).unwrap();
+        let diffs = program(&programmer, "ATF16V8B", atf, &jed, &readback, false);
+        assert_eq!(
+            diffs.unwrap(),
+            ["fuse 40: 1 -> 0, row 1 (pin 19 product 1) input pin 4"]
+        );
+
+        // a file for another part is caught before the fuses are compared.
+        let gal = crate::device::lookup("GAL22V10").unwrap();
+        let diffs = program(&programmer, "GAL22V10", gal, &jed, &readback, false);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(
+            diffs,
+            Err(ProgramError::FuseCount { found: 2194, expected: 5892, .. })
+        ));
+    }
+}
@@ -0,0 +1,682 @@
+//! A small CDCL SAT solver, with helpers for encoding gates as clauses.
+//!
+//! This is a plain conflict-driven clause learning solver: two watched literals, first-UIP
+//! learning, VSIDS branching from a heap with phase saving, Luby restarts, and deletion of
+//! the less active half of the learnt clauses as they pile up. It is kept here rather than
+//! taken from a crate because an equivalence check over a GAL unrolls to a few thousand
+//! variables, well within what this handles, and the gate helpers are all the prover needs.
+//! Each search can be given a conflict budget, so a hard instance gives up instead of
+//! running on.
+
+use std::ops::Not;
+
+/// A literal: a variable, possibly negated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Lit(u32);
+
+impl Lit {
+    fn new(var: usize, neg: bool) -> Lit {
+        Lit((var as u32) << 1 | neg as u32)
+    }
+
+    pub fn var(self) -> usize {
+        (self.0 >> 1) as usize
+    }
+
+    pub fn is_neg(self) -> bool {
+        self.0 & 1 == 1
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl Not for Lit {
+    type Output = Lit;
+
+    fn not(self) -> Lit {
+        Lit(self.0 ^ 1)
+    }
+}
+
+/// The restart interval for the `i`th restart, from the Luby sequence.
+fn luby(mut i: u64) -> u64 {
+    let mut size = 1;
+    let mut seq = 0;
+    while size < i + 1 {
+        seq += 1;
+        size = 2 * size + 1;
+    }
+    while size - 1 != i {
+        size = (size - 1) >> 1;
+        seq -= 1;
+        i %= size;
+    }
+    1 << seq
+}
+
+/// Variables to branch on, as a binary heap with the most active on top. Assigned variables
+/// are only dropped when they come to the top.
+#[derive(Default)]
+struct VarOrder {
+    heap: Vec<usize>,
+    /// Where each variable is in the heap, if it is.
+    pos: Vec<Option<usize>>,
+}
+
+impl VarOrder {
+    fn insert(&mut self, var: usize, activity: &[f64]) {
+        if self.pos.len() <= var {
+            self.pos.resize(var + 1, None);
+        }
+        if self.pos[var].is_none() {
+            self.pos[var] = Some(self.heap.len());
+            self.heap.push(var);
+            self.sift_up(self.heap.len() - 1, activity);
+        }
+    }
+
+    /// Move a variable up after its activity went up.
+    fn increased(&mut self, var: usize, activity: &[f64]) {
+        if let Some(i) = self.pos[var] {
+            self.sift_up(i, activity);
+        }
+    }
+
+    fn pop(&mut self, activity: &[f64]) -> Option<usize> {
+        let top = *self.heap.first()?;
+        let last = self.heap.pop().unwrap();
+        self.pos[top] = None;
+        if !self.heap.is_empty() {
+            self.heap[0] = last;
+            self.pos[last] = Some(0);
+            self.sift_down(0, activity);
+        }
+        Some(top)
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        self.heap.swap(i, j);
+        self.pos[self.heap[i]] = Some(i);
+        self.pos[self.heap[j]] = Some(j);
+    }
+
+    fn sift_up(&mut self, mut i: usize, activity: &[f64]) {
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if activity[self.heap[parent]] >= activity[self.heap[i]] {
+                break;
+            }
+            self.swap(i, parent);
+            i = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut i: usize, activity: &[f64]) {
+        loop {
+            let mut top = i;
+            for child in [2 * i + 1, 2 * i + 2] {
+                if child < self.heap.len() && activity[self.heap[child]] > activity[self.heap[top]] {
+                    top = child;
+                }
+            }
+            if top == i {
+                break;
+            }
+            self.swap(i, top);
+            i = top;
+        }
+    }
+}
+
+struct Clause {
+    lits: Vec<Lit>,
+    learnt: bool,
+    activity: f64,
+}
+
+pub struct Solver {
+    clauses: Vec<Clause>,
+    /// Clauses watching each literal, to visit when it becomes false.
+    watches: Vec<Vec<usize>>,
+    assigns: Vec<Option<bool>>,
+    level: Vec<usize>,
+    reason: Vec<Option<usize>>,
+    trail: Vec<Lit>,
+    trail_lim: Vec<usize>,
+    qhead: usize,
+    activity: Vec<f64>,
+    var_inc: f64,
+    order: VarOrder,
+    cla_inc: f64,
+    /// Learnt clauses to keep before deleting some.
+    max_learnts: usize,
+    phase: Vec<bool>,
+    units: Vec<Lit>,
+    unsat: bool,
+    true_lit: Lit,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Solver {
+    pub fn new() -> Self {
+        let mut s = Solver {
+            clauses: Vec::new(),
+            watches: Vec::new(),
+            assigns: Vec::new(),
+            level: Vec::new(),
+            reason: Vec::new(),
+            trail: Vec::new(),
+            trail_lim: Vec::new(),
+            qhead: 0,
+            activity: Vec::new(),
+            var_inc: 1.0,
+            order: VarOrder::default(),
+            cla_inc: 1.0,
+            max_learnts: 0,
+            phase: Vec::new(),
+            units: Vec::new(),
+            unsat: false,
+            true_lit: Lit(0),
+        };
+        s.true_lit = s.new_var();
+        s.add_clause(&[s.true_lit]);
+        s
+    }
+
+    pub fn num_vars(&self) -> usize {
+        self.assigns.len()
+    }
+
+    pub fn new_var(&mut self) -> Lit {
+        let var = self.assigns.len();
+        self.assigns.push(None);
+        self.level.push(0);
+        self.reason.push(None);
+        self.activity.push(0.0);
+        self.order.insert(var, &self.activity);
+        self.phase.push(false);
+        self.watches.push(Vec::new());
+        self.watches.push(Vec::new());
+        Lit::new(var, false)
+    }
+
+    /// A literal that is always true.
+    pub fn constant(&self, value: bool) -> Lit {
+        if value {
+            self.true_lit
+        } else {
+            !self.true_lit
+        }
+    }
+
+    pub fn add_clause(&mut self, lits: &[Lit]) {
+        let mut clause = lits.to_vec();
+        clause.sort();
+        clause.dedup();
+        if clause.windows(2).any(|w| w[0] == !w[1]) {
+            return;
+        }
+        match clause.len() {
+            0 => self.unsat = true,
+            1 => self.units.push(clause[0]),
+            _ => {
+                self.attach(clause, false);
+            }
+        }
+    }
+
+    /// Add a clause of two or more literals, watching the first two.
+    fn attach(&mut self, lits: Vec<Lit>, learnt: bool) -> usize {
+        let ci = self.clauses.len();
+        self.watches[lits[0].index()].push(ci);
+        self.watches[lits[1].index()].push(ci);
+        self.clauses.push(Clause {
+            lits,
+            learnt,
+            activity: 0.0,
+        });
+        ci
+    }
+
+    /// The value of a literal in the current assignment, which is a model after `solve`
+    /// returns true.
+    pub fn value(&self, lit: Lit) -> Option<bool> {
+        self.assigns[lit.var()].map(|v| v != lit.is_neg())
+    }
+
+    fn decision_level(&self) -> usize {
+        self.trail_lim.len()
+    }
+
+    fn enqueue(&mut self, lit: Lit, reason: Option<usize>) {
+        let var = lit.var();
+        self.assigns[var] = Some(!lit.is_neg());
+        self.level[var] = self.decision_level();
+        self.reason[var] = reason;
+        self.trail.push(lit);
+    }
+
+    /// Propagate the trail, returning a conflicting clause if there is one.
+    fn propagate(&mut self) -> Option<usize> {
+        while self.qhead < self.trail.len() {
+            let false_lit = !self.trail[self.qhead];
+            self.qhead += 1;
+            let watching = std::mem::take(&mut self.watches[false_lit.index()]);
+            let mut keep = Vec::with_capacity(watching.len());
+            for (n, &ci) in watching.iter().enumerate() {
+                let clause = &mut self.clauses[ci].lits;
+                if clause[0] == false_lit {
+                    clause.swap(0, 1);
+                }
+                let first = clause[0];
+                if self.assigns[first.var()] == Some(!first.is_neg()) {
+                    keep.push(ci);
+                    continue;
+                }
+                let assigns = &self.assigns;
+                let other = (2..clause.len()).find(|k| {
+                    let l = clause[*k];
+                    assigns[l.var()] != Some(l.is_neg())
+                });
+                if let Some(k) = other {
+                    clause.swap(1, k);
+                    let watch = clause[1];
+                    self.watches[watch.index()].push(ci);
+                    continue;
+                }
+                keep.push(ci);
+                if self.value(first) == Some(false) {
+                    keep.extend_from_slice(&watching[n + 1..]);
+                    self.watches[false_lit.index()] = keep;
+                    return Some(ci);
+                }
+                self.enqueue(first, Some(ci));
+            }
+            self.watches[false_lit.index()] = keep;
+        }
+        None
+    }
+
+    fn bump(&mut self, var: usize) {
+        self.activity[var] += self.var_inc;
+        if self.activity[var] > 1e100 {
+            for a in &mut self.activity {
+                *a *= 1e-100;
+            }
+            self.var_inc *= 1e-100;
+        }
+        self.order.increased(var, &self.activity);
+    }
+
+    fn bump_clause(&mut self, ci: usize) {
+        self.clauses[ci].activity += self.cla_inc;
+        if self.clauses[ci].activity > 1e20 {
+            for c in self.clauses.iter_mut().filter(|c| c.learnt) {
+                c.activity *= 1e-20;
+            }
+            self.cla_inc *= 1e-20;
+        }
+    }
+
+    /// Learn a first-UIP clause from a conflict, returning it and the level to go back to.
+    fn analyze(&mut self, conflict: usize) -> (Vec<Lit>, usize) {
+        let mut seen = vec![false; self.num_vars()];
+        let mut learnt = vec![Lit(0)];
+        let mut counter = 0;
+        let mut index = self.trail.len();
+        let mut clause = conflict;
+        let mut first = true;
+        let p = loop {
+            let skip = if first { 0 } else { 1 };
+            first = false;
+            if self.clauses[clause].learnt {
+                self.bump_clause(clause);
+            }
+            for k in skip..self.clauses[clause].lits.len() {
+                let q = self.clauses[clause].lits[k];
+                let var = q.var();
+                if !seen[var] && self.level[var] > 0 {
+                    seen[var] = true;
+                    self.bump(var);
+                    if self.level[var] >= self.decision_level() {
+                        counter += 1;
+                    } else {
+                        learnt.push(q);
+                    }
+                }
+            }
+            loop {
+                index -= 1;
+                if seen[self.trail[index].var()] {
+                    break;
+                }
+            }
+            let p = self.trail[index];
+            seen[p.var()] = false;
+            counter -= 1;
+            if counter == 0 {
+                break p;
+            }
+            clause = self.reason[p.var()].expect("implied literal without a reason");
+        };
+        learnt[0] = !p;
+
+        let mut back = 0;
+        if learnt.len() > 1 {
+            let max = (1..learnt.len())
+                .max_by_key(|k| self.level[learnt[*k].var()])
+                .unwrap();
+            learnt.swap(1, max);
+            back = self.level[learnt[1].var()];
+        }
+        (learnt, back)
+    }
+
+    fn backtrack(&mut self, level: usize) {
+        if self.decision_level() <= level {
+            return;
+        }
+        let lim = self.trail_lim[level];
+        for lit in self.trail.drain(lim..) {
+            self.assigns[lit.var()] = None;
+            self.reason[lit.var()] = None;
+            self.phase[lit.var()] = !lit.is_neg();
+            self.order.insert(lit.var(), &self.activity);
+        }
+        self.trail_lim.truncate(level);
+        self.qhead = self.trail.len();
+    }
+
+    fn pick_branch(&mut self) -> Option<Lit> {
+        while let Some(var) = self.order.pop(&self.activity) {
+            if self.assigns[var].is_none() {
+                return Some(Lit::new(var, !self.phase[var]));
+            }
+        }
+        None
+    }
+
+    /// Delete the less active half of the learnt clauses, apart from binary ones. This is
+    /// only done at level 0, where no assignment has a reason that analysis looks at.
+    fn reduce_learnts(&mut self) {
+        let mut learnts: Vec<usize> = (0..self.clauses.len())
+            .filter(|ci| self.clauses[*ci].learnt && self.clauses[*ci].lits.len() > 2)
+            .collect();
+        learnts.sort_by(|a, b| self.clauses[*a].activity.total_cmp(&self.clauses[*b].activity));
+        let mut delete = vec![false; self.clauses.len()];
+        for ci in &learnts[..learnts.len() / 2] {
+            delete[*ci] = true;
+        }
+        let mut kept = 0;
+        let mut remap = vec![None; self.clauses.len()];
+        for (ci, new) in remap.iter_mut().enumerate() {
+            if !delete[ci] {
+                *new = Some(kept);
+                kept += 1;
+            }
+        }
+        let mut ci = 0;
+        self.clauses.retain(|_| {
+            ci += 1;
+            !delete[ci - 1]
+        });
+        for reason in &mut self.reason {
+            *reason = reason.and_then(|ci| remap[ci]);
+        }
+        for w in &mut self.watches {
+            w.clear();
+        }
+        for (ci, clause) in self.clauses.iter().enumerate() {
+            self.watches[clause.lits[0].index()].push(ci);
+            self.watches[clause.lits[1].index()].push(ci);
+        }
+    }
+
+    /// Search for an assignment satisfying every clause, returning true if there is one.
+    #[cfg(test)]
+    pub fn solve(&mut self) -> bool {
+        self.solve_within(u64::MAX)
+            .expect("an unlimited search always finishes")
+    }
+
+    /// Like `solve`, but give up with `None` after `max_conflicts` conflicts.
+    pub fn solve_within(&mut self, max_conflicts: u64) -> Option<bool> {
+        if self.unsat {
+            return Some(false);
+        }
+        self.backtrack(0);
+        for lit in std::mem::take(&mut self.units) {
+            match self.value(lit) {
+                Some(false) => {
+                    self.unsat = true;
+                    return Some(false);
+                }
+                Some(true) => {}
+                None => self.enqueue(lit, None),
+            }
+        }
+
+        self.max_learnts = self.max_learnts.max(self.clauses.len() / 3).max(1000);
+        let mut total = 0;
+        let mut conflicts = 0;
+        let mut restarts = 0;
+        let mut limit = 100 * luby(restarts);
+        loop {
+            if let Some(conflict) = self.propagate() {
+                if self.decision_level() == 0 {
+                    self.unsat = true;
+                    return Some(false);
+                }
+                let (learnt, back) = self.analyze(conflict);
+                self.backtrack(back);
+                if learnt.len() == 1 {
+                    self.enqueue(learnt[0], None);
+                } else {
+                    let first = learnt[0];
+                    let ci = self.attach(learnt, true);
+                    self.bump_clause(ci);
+                    self.enqueue(first, Some(ci));
+                }
+                self.var_inc /= 0.95;
+                self.cla_inc /= 0.999;
+                total += 1;
+                if total >= max_conflicts {
+                    self.backtrack(0);
+                    return None;
+                }
+                conflicts += 1;
+                if conflicts >= limit {
+                    conflicts = 0;
+                    restarts += 1;
+                    limit = 100 * luby(restarts);
+                    self.backtrack(0);
+                    let learnts = self.clauses.iter().filter(|c| c.learnt).count();
+                    if learnts > self.max_learnts {
+                        self.reduce_learnts();
+                        self.max_learnts += self.max_learnts / 10;
+                    }
+                }
+            } else {
+                let Some(lit) = self.pick_branch() else {
+                    return Some(true);
+                };
+                self.trail_lim.push(self.trail.len());
+                self.enqueue(lit, None);
+            }
+        }
+    }
+
+    /// A literal that is true when all of `lits` are.
+    pub fn and(&mut self, lits: &[Lit]) -> Lit {
+        match lits {
+            [] => self.constant(true),
+            [l] => *l,
+            _ => {
+                let out = self.new_var();
+                let mut all = vec![out];
+                for l in lits {
+                    self.add_clause(&[!out, *l]);
+                    all.push(!*l);
+                }
+                self.add_clause(&all);
+                out
+            }
+        }
+    }
+
+    /// A literal that is true when any of `lits` is.
+    pub fn or(&mut self, lits: &[Lit]) -> Lit {
+        let inv: Vec<Lit> = lits.iter().map(|l| !*l).collect();
+        !self.and(&inv)
+    }
+
+    pub fn xor(&mut self, a: Lit, b: Lit) -> Lit {
+        let out = self.new_var();
+        self.add_clause(&[!out, a, b]);
+        self.add_clause(&[!out, !a, !b]);
+        self.add_clause(&[out, !a, b]);
+        self.add_clause(&[out, a, !b]);
+        out
+    }
+
+    /// `t` when `c` is true, otherwise `e`.
+    pub fn ite(&mut self, c: Lit, t: Lit, e: Lit) -> Lit {
+        let out = self.new_var();
+        self.add_clause(&[!c, !t, out]);
+        self.add_clause(&[!c, t, !out]);
+        self.add_clause(&[c, !e, out]);
+        self.add_clause(&[c, e, !out]);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_luby() {
+        let seq: Vec<u64> = (0..15).map(luby).collect();
+        assert_eq!(seq, [1, 1, 2, 1, 1, 2, 4, 1, 1, 2, 1, 1, 2, 4, 8]);
+    }
+
+    /// Clauses saying `pigeons` pigeons each sit in one of `holes` holes, no two together.
+    fn pigeonhole(pigeons: usize, holes: usize) -> Solver {
+        let mut s = Solver::new();
+        let p: Vec<Vec<Lit>> = (0..pigeons)
+            .map(|_| (0..holes).map(|_| s.new_var()).collect())
+            .collect();
+        for pigeon in &p {
+            s.add_clause(pigeon);
+        }
+        for (a, pa) in p.iter().enumerate() {
+            for pb in &p[a + 1..] {
+                for (x, y) in pa.iter().zip(pb) {
+                    s.add_clause(&[!*x, !*y]);
+                }
+            }
+        }
+        s
+    }
+
+    #[test]
+    fn test_pigeonhole() {
+        // four pigeons don't fit in three holes.
+        assert!(!pigeonhole(4, 3).solve());
+        assert!(pigeonhole(3, 3).solve());
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut s = pigeonhole(7, 6);
+        assert_eq!(s.solve_within(10), None);
+        // giving up leaves the solver usable.
+        assert_eq!(s.solve_within(u64::MAX), Some(false));
+    }
+
+    #[test]
+    fn test_reduce_learnts() {
+        let mut s = pigeonhole(8, 7);
+        assert!(!s.solve());
+        let learnts = s.clauses.iter().filter(|c| c.learnt).count();
+        assert!(learnts <= s.max_learnts, "{learnts} learnt clauses kept");
+        assert!(s.max_learnts > 1000, "never reduced");
+    }
+
+    #[test]
+    fn test_var_order() {
+        let activity = [3.0, 1.0, 4.0, 1.5, 5.0];
+        let mut order = VarOrder::default();
+        for var in 0..5 {
+            order.insert(var, &activity);
+        }
+        order.insert(2, &activity);
+        let popped: Vec<usize> = std::iter::from_fn(|| order.pop(&activity)).collect();
+        assert_eq!(popped, [4, 2, 0, 3, 1]);
+    }
+
+    #[test]
+    fn test_random_3sat() {
+        // compare against brute force on small random instances.
+        let mut x: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut rand = |n: u64| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x % n
+        };
+        for _ in 0..200 {
+            let nvars = 8;
+            let clauses: Vec<Vec<(usize, bool)>> = (0..36)
+                .map(|_| {
+                    (0..3)
+                        .map(|_| (rand(nvars) as usize, rand(2) == 1))
+                        .collect()
+                })
+                .collect();
+            let brute = (0..1u32 << nvars).any(|m| {
+                clauses
+                    .iter()
+                    .all(|c| c.iter().any(|(v, neg)| (m >> v & 1 == 1) != *neg))
+            });
+
+            let mut s = Solver::new();
+            let vars: Vec<Lit> = (0..nvars).map(|_| s.new_var()).collect();
+            for c in &clauses {
+                let lits: Vec<Lit> = c
+                    .iter()
+                    .map(|(v, neg)| if *neg { !vars[*v] } else { vars[*v] })
+                    .collect();
+                s.add_clause(&lits);
+            }
+            assert_eq!(s.solve(), brute);
+            if brute {
+                for c in &clauses {
+                    assert!(c.iter().any(|(v, neg)| s.value(vars[*v]) == Some(!*neg)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gates() {
+        let mut s = Solver::new();
+        let a = s.new_var();
+        let b = s.new_var();
+        let x = s.xor(a, b);
+        let both = s.and(&[a, b]);
+        let either = s.or(&[a, b]);
+        // a xor b is the same as (a or b) and not (a and b).
+        let other = s.and(&[either, !both]);
+        let differ = s.xor(x, other);
+        s.add_clause(&[differ]);
+        assert!(!s.solve());
+    }
+}
@@ -0,0 +1,135 @@
+//! The user electronic signature: a row of 64 fuses that does nothing but can be read back,
+//! so a programmed part can be matched to the design revision it carries.
+
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+/// Bytes in the signature row.
+pub const SIGNATURE_BYTES: usize = 8;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SignatureError {
+    #[error("Signature {0} is longer than 8 bytes")]
+    TooLong(String),
+
+    #[error("Signature {0} is not valid hex")]
+    BadHex(String),
+}
+
+/// Read a `--signature` argument: `0x` followed by hex digits, `auto`, or else text.
+pub fn parse(arg: &str) -> Result<Vec<u8>, SignatureError> {
+    let bytes = if arg == "auto" {
+        auto()
+    } else if let Some(hex) = arg.strip_prefix("0x") {
+        if hex.len() % 2 == 1 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(SignatureError::BadHex(arg.to_string()));
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    } else {
+        arg.as_bytes().to_vec()
+    };
+    if bytes.len() > SIGNATURE_BYTES {
+        return Err(SignatureError::TooLong(arg.to_string()));
+    }
+    Ok(bytes)
+}
+
+/// The short hash of the git commit being built, or failing that the date.
+pub fn auto() -> Vec<u8> {
+    let hash = Command::new("git")
+        .args(["rev-parse", &format!("--short={SIGNATURE_BYTES}"), "HEAD"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+        .filter(|hash| !hash.is_empty());
+    let text = hash.unwrap_or_else(|| {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        build_date(secs)
+    });
+    text.bytes().take(SIGNATURE_BYTES).collect()
+}
+
+/// The UTC date as `YYYYMMDD`, from seconds since the epoch.
+pub fn build_date(secs: u64) -> String {
+    // days to a civil date, from Howard Hinnant's `civil_from_days`.
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}{month:02}{day:02}")
+}
+
+/// Pack the signature fuses into bytes, most significant bit first, as galette lays them out.
+pub fn from_fuses(fuses: &[bool]) -> Vec<u8> {
+    fuses
+        .chunks(8)
+        .map(|byte| byte.iter().fold(0u8, |b, f| b << 1 | *f as u8))
+        .collect()
+}
+
+/// Show a signature as text if it is printable, and always as hex.
+pub fn describe(sig: &[u8]) -> String {
+    let hex: Vec<String> = sig.iter().map(|b| format!("{b:02x}")).collect();
+    let hex = hex.join(" ");
+    let text = match sig.iter().rposition(|b| *b != 0) {
+        Some(end) => &sig[..=end],
+        None => return format!("blank ({hex})"),
+    };
+    if text.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        format!("\"{}\" ({hex})", String::from_utf8_lossy(text))
+    } else {
+        hex
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("rev3").unwrap(), b"rev3");
+        assert_eq!(parse("0x00ff10").unwrap(), [0x00, 0xff, 0x10]);
+        assert_eq!(
+            parse("0xfff").unwrap_err(),
+            SignatureError::BadHex("0xfff".to_string())
+        );
+        assert_eq!(
+            parse("much too long").unwrap_err(),
+            SignatureError::TooLong("much too long".to_string())
+        );
+        assert!(parse("auto").unwrap().len() <= SIGNATURE_BYTES);
+    }
+
+    #[test]
+    fn test_build_date() {
+        assert_eq!(build_date(0), "19700101");
+        assert_eq!(build_date(951_782_400), "20000229");
+        assert_eq!(build_date(1_792_368_000), "20261019");
+    }
+
+    #[test]
+    fn test_describe() {
+        let mut fuses = vec![false; 64];
+        // 'A' is 0x41, most significant bit first.
+        fuses[1] = true;
+        fuses[7] = true;
+        let sig = from_fuses(&fuses);
+        assert_eq!(sig, [0x41, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(describe(&sig), "\"A\" (41 00 00 00 00 00 00 00)");
+        assert_eq!(describe(&[0; 2]), "blank (00 00)");
+        assert_eq!(describe(&[1, 2]), "01 02");
+    }
+}
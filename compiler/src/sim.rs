@@ -0,0 +1,456 @@
+//! Simulate a fitted design at the pin level, and generate JEDEC test vectors from it.
+//!
+//! The simulation works from the blueprint, like the JEDEC writer, using three-valued logic:
+//! registers start at the chip's power-up value, and anything that reads an unknown or
+//! floating pin may be unknown too. The array reads combinational outputs back
+//! from their pins, and registered outputs from the register, as the chips do.
+
+use crate::fitter::{chip_to_olmc_offset, power_up_level, Fit};
+use crate::yosys_parser::{Graph, PortDirection};
+use galette::blueprint::{Active, PinMode};
+use galette::chips::Chip;
+use galette::gal::Term;
+use std::collections::BTreeMap;
+
+/// Logic level on a pin, or `None` if it is unknown.
+pub type Level = Option<bool>;
+
+/// What an output pin is doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinState {
+    Low,
+    High,
+    HighZ,
+    Unknown,
+}
+
+impl PinState {
+    /// The JEDEC test vector character that checks for this state.
+    pub fn vector_char(self) -> char {
+        match self {
+            PinState::Low => 'L',
+            PinState::High => 'H',
+            PinState::HighZ => 'Z',
+            PinState::Unknown => 'X',
+        }
+    }
+}
+
+/// Evaluate a sum of products over the pin levels.
+fn eval(term: &Term, pins: &BTreeMap<u32, Level>) -> Level {
+    let mut result = Some(false);
+    for product in &term.pins {
+        let mut value = Some(true);
+        for p in product {
+            let lit = pins
+                .get(&(p.pin as u32))
+                .copied()
+                .flatten()
+                .map(|v| v != p.neg);
+            value = match (value, lit) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            };
+        }
+        result = match (result, value) {
+            (Some(true), _) | (_, Some(true)) => Some(true),
+            (Some(false), Some(false)) => Some(false),
+            _ => None,
+        };
+    }
+    result
+}
+
+pub struct Simulator<'a> {
+    fit: &'a Fit,
+    /// Register contents for each OLMC row, as the sum before the output polarity is applied.
+    regs: Vec<Level>,
+}
+
+impl<'a> Simulator<'a> {
+    /// Start a simulation from power-up.
+    pub fn new(fit: &'a Fit) -> Self {
+        let regs = fit
+            .blueprint
+            .olmcs
+            .iter()
+            .map(|o| {
+                let inverted = o.active == Active::Low;
+                Some(power_up_level(fit.chip, inverted) != inverted)
+            })
+            .collect();
+        Simulator { fit, regs }
+    }
+
+    fn offset(&self) -> u32 {
+        chip_to_olmc_offset(&self.fit.chip) as u32
+    }
+
+    /// The level the array reads back from a registered row. Both chips feed back the
+    /// register's inverted output, but the 16V8's XOR is before the register, so that matches
+    /// the pin, while the 22V10's is after it, so it is the inverse of the sum.
+    fn feedback(&self, row: usize, active: Active) -> Level {
+        match self.fit.chip {
+            Chip::GAL22V10 => self.regs[row].map(|v| !v),
+            _ => self.regs[row].map(|v| v != (active == Active::Low)),
+        }
+    }
+
+    /// Work out the outputs from the inputs and registers, repeating until combinational
+    /// feedback settles. Each pin comes with the level the array reads back from it.
+    fn settle(&self, inputs: &BTreeMap<u32, bool>) -> BTreeMap<u32, (PinState, Level)> {
+        let mut pins: BTreeMap<u32, Level> = inputs.iter().map(|(p, v)| (*p, Some(*v))).collect();
+        let mut states = BTreeMap::new();
+        for _ in 0..=self.fit.blueprint.olmcs.len() {
+            let mut changed = false;
+            for (row, olmc) in self.fit.blueprint.olmcs.iter().enumerate() {
+                let Some((mode, term)) = &olmc.output else {
+                    continue;
+                };
+                let pin = row as u32 + self.offset();
+                let raw = match mode {
+                    PinMode::Registered => self.regs[row],
+                    _ => eval(term, &pins),
+                };
+                let value = raw.map(|v| v != (olmc.active == Active::Low));
+                let enable = olmc
+                    .tri_con
+                    .as_ref()
+                    .map_or(Some(true), |en| eval(en, &pins));
+                let state = match (enable, value) {
+                    (Some(false), _) => PinState::HighZ,
+                    (Some(true), Some(true)) => PinState::High,
+                    (Some(true), Some(false)) => PinState::Low,
+                    _ => PinState::Unknown,
+                };
+                // a floating pin reads back as unknown.
+                let level = match mode {
+                    PinMode::Registered => self.feedback(row, olmc.active),
+                    _ if enable == Some(true) => value,
+                    _ => None,
+                };
+                if states.insert(pin, (state, level)) != Some((state, level)) {
+                    changed = true;
+                }
+                pins.insert(pin, level);
+            }
+            if !changed {
+                break;
+            }
+        }
+        states
+    }
+
+    /// Apply the inputs, optionally pulse the clock, and return the state of each output pin.
+    pub fn step(&mut self, inputs: &BTreeMap<u32, bool>, clock: bool) -> BTreeMap<u32, PinState> {
+        let mut states = self.settle(inputs);
+        if clock {
+            let mut pins: BTreeMap<u32, Level> =
+                inputs.iter().map(|(p, v)| (*p, Some(*v))).collect();
+            pins.extend(states.iter().map(|(p, (_, l))| (*p, *l)));
+            for (row, olmc) in self.fit.blueprint.olmcs.iter().enumerate() {
+                if let Some((PinMode::Registered, term)) = &olmc.output {
+                    self.regs[row] = eval(term, &pins);
+                }
+            }
+            states = self.settle(inputs);
+        }
+        states.into_iter().map(|(p, (s, _))| (p, s)).collect()
+    }
+}
+
+/// Generate up to `count` test vectors. Combinational designs are tested exhaustively when
+/// that fits, otherwise with a fixed pseudo-random sequence that is clocked on every vector
+/// for registered designs.
+pub fn test_vectors(fit: &Fit, graph: &Graph, count: usize) -> Vec<String> {
+    let chip = fit.chip;
+    let num_pins = chip.num_pins() as u32;
+    let registered = fit
+        .blueprint
+        .olmcs
+        .iter()
+        .any(|o| matches!(o.output, Some((PinMode::Registered, _))));
+    let clock_pin = registered.then_some(1);
+    // the 16V8 is always used in registered mode, where pin 11 is the output enable.
+    let oe_pin = (chip == Chip::GAL16V8).then_some(11);
+
+    let mut inputs: Vec<u32> = graph
+        .ports
+        .iter()
+        .filter(|p| p.direction == PortDirection::Input)
+        .filter_map(|p| p.lookup(&fit.pcf))
+        .filter(|p| Some(*p) != clock_pin && Some(*p) != oe_pin)
+        .collect();
+    inputs.sort();
+    inputs.dedup();
+
+    let patterns: Vec<u64> =
+        if !registered && inputs.len() < 64 && 1u64 << inputs.len() <= count as u64 {
+            (0..1u64 << inputs.len()).collect()
+        } else {
+            let mut x: u64 = 0x2545_f491_4f6c_dd1d;
+            (0..count)
+                .map(|_| {
+                    x ^= x << 13;
+                    x ^= x >> 7;
+                    x ^= x << 17;
+                    x
+                })
+                .collect()
+        };
+
+    let mut sim = Simulator::new(fit);
+    patterns
+        .into_iter()
+        .map(|pattern| {
+            let mut drive: BTreeMap<u32, bool> = inputs
+                .iter()
+                .enumerate()
+                .map(|(i, pin)| (*pin, pattern >> (i % 64) & 1 == 1))
+                .collect();
+            if let Some(oe) = oe_pin {
+                drive.insert(oe, false);
+            }
+            let states = sim.step(&drive, registered);
+            (1..=num_pins)
+                .map(|pin| {
+                    if pin == num_pins || pin == num_pins / 2 {
+                        'N'
+                    } else if Some(pin) == clock_pin {
+                        'C'
+                    } else if let Some(v) = drive.get(&pin) {
+                        if *v {
+                            '1'
+                        } else {
+                            '0'
+                        }
+                    } else if let Some(state) = states.get(&pin) {
+                        state.vector_char()
+                    } else {
+                        'X'
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fitter::{auto_assign, fit};
+    use crate::pcf::parse_pcf;
+    use crate::sop::{Cover, Literal};
+    use crate::equiv::{prove, Verdict};
+    use crate::jedec::{galette_jedec, parse};
+    use crate::test_util::{self, hand_netlist, named, olmc, ports, sop, testcase_pcf};
+    use crate::yosys_parser::Net;
+    use galette::blueprint::Blueprint;
+    use galette::gal::{Mode, Pin};
+    use galette::gal_builder::build;
+
+    /// A graph with just these ports, on nets from 2 up.
+    fn graph(list: &[(&str, PortDirection)]) -> Graph {
+        let mut g = Graph::default();
+        let list: Vec<(&str, u32, PortDirection)> = list
+            .iter()
+            .enumerate()
+            .map(|(i, (name, direction))| (*name, i as u32 + 2, direction.clone()))
+            .collect();
+        ports(&mut g, &list);
+        g
+    }
+
+    #[test]
+    fn test_combinational_vectors() {
+        let g = graph(&[
+            ("a", PortDirection::Input),
+            ("b", PortDirection::Input),
+            ("y", PortDirection::Output),
+        ]);
+        let mut bp = Blueprint::new(Chip::GAL22V10);
+        let and = Term {
+            line_num: 0,
+            pins: vec![vec![Pin { pin: 2, neg: false }, Pin { pin: 3, neg: false }]],
+        };
+        bp.olmcs[9]
+            .set_base(&Pin { pin: 0, neg: true }, and, PinMode::Tristate)
+            .unwrap();
+        let fit = Fit {
+            chip: Chip::GAL22V10,
+            blueprint: bp,
+            olmcmap: vec![None; 10],
+            pcf: parse_pcf("set_io a 2\nset_io b 3\nset_io y 23"),
+        };
+        let v = test_vectors(&fit, &g, 16);
+        // exhaustive over the two inputs, with the output inverted.
+        assert_eq!(v.len(), 4);
+        assert_eq!(v[0], "X00XXXXXXXXNXXXXXXXXXXHN");
+        assert_eq!(v[3], "X11XXXXXXXXNXXXXXXXXXXLN");
+    }
+
+    #[test]
+    fn test_registered_vectors() {
+        let g = graph(&[("d", PortDirection::Input), ("q", PortDirection::Output)]);
+        let mut bp = Blueprint::new(Chip::GAL16V8);
+        let d = Term {
+            line_num: 0,
+            pins: vec![vec![Pin { pin: 2, neg: false }]],
+        };
+        bp.olmcs[7]
+            .set_base(&Pin { pin: 0, neg: false }, d, PinMode::Registered)
+            .unwrap();
+        let fit = Fit {
+            chip: Chip::GAL16V8,
+            blueprint: bp,
+            olmcmap: vec![None; 8],
+            pcf: parse_pcf("set_io d 2\nset_io q 19"),
+        };
+        let v = test_vectors(&fit, &g, 8);
+        assert_eq!(v.len(), 8);
+        for vector in v {
+            assert_eq!(&vector[..1], "C");
+            assert_eq!(&vector[10..11], "0");
+            // q follows d on every clock.
+            let want = if &vector[1..2] == "1" { "H" } else { "L" };
+            assert_eq!(&vector[18..19], want, "{vector}");
+        }
+    }
+
+    #[test]
+    fn test_power_up() {
+        let mut bp = Blueprint::new(Chip::GAL16V8);
+        // a toggle flip-flop, which starts from the 16V8's high power-up level.
+        let t = Term {
+            line_num: 0,
+            pins: vec![vec![Pin { pin: 19, neg: true }]],
+        };
+        bp.olmcs[7]
+            .set_base(&Pin { pin: 0, neg: false }, t, PinMode::Registered)
+            .unwrap();
+        let fit = Fit {
+            chip: Chip::GAL16V8,
+            blueprint: bp,
+            olmcmap: vec![None; 8],
+            pcf: Default::default(),
+        };
+        let mut sim = Simulator::new(&fit);
+        assert_eq!(sim.step(&BTreeMap::new(), false)[&19], PinState::High);
+        assert_eq!(sim.step(&BTreeMap::new(), true)[&19], PinState::Low);
+        assert_eq!(sim.step(&BTreeMap::new(), true)[&19], PinState::High);
+        sim.regs = vec![None; 8];
+        assert_eq!(sim.step(&BTreeMap::new(), true)[&19], PinState::Unknown);
+    }
+
+    /// A three bit up counter, as yosys maps `testcases/up_counter_upto.v`: each bit is a
+    /// register whose SOP reads the counter back. `inverted` picks the OLMC polarity.
+    fn counter(inverted: bool) -> Graph {
+        use Literal::{DontCare as X, One as I, Zero as O};
+        let mut g = Graph::default();
+        ports(&mut g, &[("clk", 1, PortDirection::Input)]);
+        // the next value of each bit, over the current bits.
+        let next = [
+            vec![vec![O, X, X]],
+            vec![vec![I, O, X], vec![O, I, X]],
+            vec![vec![O, X, I], vec![X, O, I], vec![I, I, O]],
+        ];
+        for (i, cubes) in next.into_iter().enumerate() {
+            let cover = Cover { width: 3, cubes };
+            // the OLMC inverts, so the SOP gives the inverse of the next value.
+            let cover = if inverted { cover.complement().unwrap() } else { cover };
+            let (d, q) = (30 + i as u32, 20 + i as u32);
+            let next = sop(&[20, 21, 22], d, &cover.to_params().table);
+            g.nodelist.push(named(next, &format!("next{i}")));
+            let reg = named(olmc(d, Net::LiteralOne, q, true), &format!("counter{i}"));
+            g.nodelist.push(if inverted { test_util::inverted(reg) } else { reg });
+            ports(&mut g, &[(&format!("counter[{i}]"), q, PortDirection::Output)]);
+        }
+        g.generate_adjacency();
+        g
+    }
+
+    #[test]
+    fn test_counter_feedback() {
+        for chip in [Chip::GAL16V8, Chip::GAL22V10] {
+            for inverted in [false, true] {
+                let g = counter(inverted);
+                let pcf = auto_assign(&g, chip).unwrap();
+                let fit = fit(&g, &pcf, chip).unwrap();
+                let pins: Vec<u32> = (0..3)
+                    .map(|i| pcf.pin(&format!("counter[{i}]")).unwrap())
+                    .collect();
+                let mut sim = Simulator::new(&fit);
+                let mut count = |clock| {
+                    let states = sim.step(&BTreeMap::new(), clock);
+                    pins.iter().enumerate().fold(0, |n, (i, pin)| match states[pin] {
+                        PinState::High => n | 1 << i,
+                        PinState::Low => n,
+                        other => panic!("counter[{i}] is {other:?}"),
+                    })
+                };
+                // the 16V8 powers up with every output high, the 22V10 with the registers clear.
+                let start = count(false);
+                let want_start = match (chip, inverted) {
+                    (Chip::GAL22V10, false) => 0,
+                    _ => 7,
+                };
+                assert_eq!(start, want_start, "{} inverted={inverted}", chip.name());
+                for n in 1..=9 {
+                    assert_eq!(count(true), (start + n) % 8, "{} inverted={inverted}", chip.name());
+                }
+            }
+        }
+    }
+
+    /// The up counters from `testcases/up_counter_downto.v` and `testcases/up_counter_upto.v`,
+    /// fitted, built into a JEDEC file that is checked against the netlist, then clocked.
+    /// `counter[0]` is the LSB of the downto counter and the MSB of the upto one.
+    #[test]
+    fn test_up_counter_testcases() {
+        for (netlist, pcf, width, upto) in [
+            ("up_counter_downto", "up_counter", 7, false),
+            ("up_counter_upto", "up_counter_upto", 8, true),
+        ] {
+            for chip in [Chip::GAL16V8, Chip::GAL22V10] {
+                let g = hand_netlist(netlist);
+                let pcf = match chip {
+                    Chip::GAL16V8 => testcase_pcf(pcf),
+                    _ => auto_assign(&g, chip).unwrap(),
+                };
+                let fit = fit(&g, &pcf, chip).unwrap();
+                let mut gal = build(&fit.blueprint).unwrap();
+                if chip == Chip::GAL16V8 {
+                    gal.set_mode(Mode::Registered);
+                }
+                let fuses = parse(&galette_jedec(&gal)).unwrap().fuses;
+                let verdict = prove(&g, &pcf, &fuses, 8).unwrap();
+                assert!(matches!(verdict, Verdict::Equivalent(_)), "{netlist}: {verdict:?}");
+
+                // the pin for each bit of the count, LSB first.
+                let pins: Vec<u32> = (0..width)
+                    .map(|i| if upto { width - 1 - i } else { i })
+                    .map(|i| pcf.pin(&format!("counter[{i}]")).unwrap())
+                    .collect();
+                let mut sim = Simulator::new(&fit);
+                let mut count = |clock| {
+                    let states = sim.step(&BTreeMap::new(), clock);
+                    pins.iter().enumerate().fold(0, |n, (i, pin)| match states[pin] {
+                        PinState::High => n | 1 << i,
+                        PinState::Low => n,
+                        other => panic!("{netlist}: bit {i} is {other:?}"),
+                    })
+                };
+                let start = count(false);
+                let want_start = match chip {
+                    Chip::GAL22V10 => 0,
+                    _ => (1 << width) - 1,
+                };
+                assert_eq!(start, want_start, "{netlist} on {}", chip.name());
+                for n in 1..=10 {
+                    assert_eq!(count(true), (start + n) % (1 << width), "{netlist} on {}", chip.name());
+                }
+            }
+        }
+    }
+}
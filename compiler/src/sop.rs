@@ -0,0 +1,509 @@
+//! Cube representation of GAL_SOP truth tables.
+//!
+//! A `Cover` is a list of product terms (cubes) over the bits of a SOP's `A` connection.
+//! Cube literals are indexed by bit position in `A`, i.e. literal `j` is `A[j]`.
+use crate::yosys_parser::GalSopParameters;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Literal {
+    /// The input must be low.
+    Zero,
+    /// The input must be high.
+    One,
+    /// The input is not used by this product.
+    DontCare,
+}
+
+impl Literal {
+    fn from_table(seg: &[u8]) -> Option<Literal> {
+        // see make_term_from_sop, a product with both bits set can never be true.
+        match seg {
+            b"00" => Some(Literal::DontCare),
+            b"01" => Some(Literal::Zero),
+            b"10" => Some(Literal::One),
+            _ => None,
+        }
+    }
+
+    fn to_table(self) -> &'static str {
+        match self {
+            Literal::DontCare => "00",
+            Literal::Zero => "01",
+            Literal::One => "10",
+        }
+    }
+
+    fn invert(self) -> Literal {
+        match self {
+            Literal::Zero => Literal::One,
+            Literal::One => Literal::Zero,
+            Literal::DontCare => Literal::DontCare,
+        }
+    }
+}
+
+pub type Cube = Vec<Literal>;
+
+/// Recursion steps the complement and tautology checks may take before giving up. Both are
+/// exponential in the worst case, and a 22V10 SOP can have 22 inputs.
+const RECURSION_BUDGET: usize = 4096;
+
+/// The most cubes a complement may grow to before giving up. No row holds more than 16
+/// products, so a bigger complement is no use anyway.
+const MAX_COMPLEMENT_CUBES: usize = 256;
+
+/// A sum of products over `width` inputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cover {
+    pub width: usize,
+    pub cubes: Vec<Cube>,
+}
+
+/// Returns true if the two cubes share at least one minterm.
+fn cube_intersects(a: &Cube, b: &Cube) -> bool {
+    a.iter()
+        .zip(b)
+        .all(|(x, y)| *x == Literal::DontCare || *y == Literal::DontCare || x == y)
+}
+
+/// The intersection of two cubes, or `None` if they are disjoint.
+pub fn cube_and(a: &Cube, b: &Cube) -> Option<Cube> {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| match (x, y) {
+            (Literal::DontCare, l) | (l, Literal::DontCare) => Some(*l),
+            (x, y) if x == y => Some(*x),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns true if every minterm of `b` is also in `a`.
+fn cube_contains(a: &Cube, b: &Cube) -> bool {
+    a.iter()
+        .zip(b)
+        .all(|(x, y)| *x == Literal::DontCare || x == y)
+}
+
+impl Cover {
+    /// The constant false cover.
+    pub fn empty(width: usize) -> Cover {
+        Cover {
+            width,
+            cubes: Vec::new(),
+        }
+    }
+
+    /// The constant true cover.
+    pub fn tautology(width: usize) -> Cover {
+        Cover {
+            width,
+            cubes: vec![vec![Literal::DontCare; width]],
+        }
+    }
+
+    /// Parse the `TABLE` parameter of a GAL_SOP. Products that can never be true are dropped.
+    pub fn from_params(params: &GalSopParameters) -> Cover {
+        let width = params.width as usize;
+        let table = params.table.as_bytes();
+        let mut cubes = Vec::new();
+        if width > 0 {
+            for chunk in table.chunks(width * 2) {
+                // the table is written MSB first, so the first pair is the last bit of A.
+                let cube: Option<Cube> = chunk.chunks(2).rev().map(Literal::from_table).collect();
+                if let Some(c) = cube {
+                    cubes.push(c);
+                }
+            }
+        }
+        Cover { width, cubes }
+    }
+
+    /// Convert back into the yosys parameter encoding.
+    pub fn to_params(&self) -> GalSopParameters {
+        let table = self
+            .cubes
+            .iter()
+            .flat_map(|c| c.iter().rev().map(|l| l.to_table()))
+            .collect();
+        GalSopParameters {
+            depth: self.cubes.len() as u32,
+            table,
+            width: self.width as u32,
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.cubes.len()
+    }
+
+    /// Evaluate the cover for the given input values, indexed like `A`.
+    #[cfg(test)]
+    pub fn eval(&self, inputs: &[bool]) -> bool {
+        self.cubes.iter().any(|c| {
+            c.iter().zip(inputs).all(|(l, v)| match l {
+                Literal::Zero => !v,
+                Literal::One => *v,
+                Literal::DontCare => true,
+            })
+        })
+    }
+
+    /// Restrict the cover to the half of the input space where `var` has the given value.
+    fn cofactor(&self, var: usize, value: bool) -> Cover {
+        let (keep, drop) = if value {
+            (Literal::One, Literal::Zero)
+        } else {
+            (Literal::Zero, Literal::One)
+        };
+        let cubes = self
+            .cubes
+            .iter()
+            .filter(|c| c[var] != drop)
+            .map(|c| {
+                let mut c = c.clone();
+                if c[var] == keep {
+                    c[var] = Literal::DontCare;
+                }
+                c
+            })
+            .collect();
+        Cover {
+            width: self.width,
+            cubes,
+        }
+    }
+
+    /// Pick the variable used by the most cubes, preferring ones used in both polarities.
+    fn split_variable(&self) -> Option<usize> {
+        (0..self.width)
+            .map(|v| {
+                let ones = self.cubes.iter().filter(|c| c[v] == Literal::One).count();
+                let zeros = self.cubes.iter().filter(|c| c[v] == Literal::Zero).count();
+                (v, (ones.min(zeros), ones + zeros))
+            })
+            .filter(|(_, (_, used))| *used > 0)
+            .max_by_key(|(_, score)| *score)
+            .map(|(v, _)| v)
+    }
+
+    /// Remove cubes contained in another cube of the cover.
+    pub fn remove_contained(&mut self) {
+        let mut kept: Vec<Cube> = Vec::with_capacity(self.cubes.len());
+        for c in self.cubes.drain(..) {
+            if kept.iter().any(|k| cube_contains(k, &c)) {
+                continue;
+            }
+            kept.retain(|k| !cube_contains(&c, k));
+            kept.push(c);
+        }
+        self.cubes = kept;
+    }
+
+    /// Merge pairs of cubes that differ only in the polarity of one literal, until none remain.
+    pub fn merge_adjacent(&mut self) {
+        loop {
+            let mut merged = None;
+            'search: for i in 0..self.cubes.len() {
+                for j in i + 1..self.cubes.len() {
+                    let (a, b) = (&self.cubes[i], &self.cubes[j]);
+                    let diff: Vec<usize> = (0..self.width).filter(|v| a[*v] != b[*v]).collect();
+                    if let [v] = diff[..] {
+                        if a[v] != Literal::DontCare && b[v] != Literal::DontCare {
+                            merged = Some((i, j, v));
+                            break 'search;
+                        }
+                    }
+                }
+            }
+            let Some((i, j, v)) = merged else {
+                break;
+            };
+            self.cubes[i][v] = Literal::DontCare;
+            self.cubes.remove(j);
+            self.remove_contained();
+        }
+    }
+
+    /// Compute the complement of the cover, using recursive Shannon expansion. Returns `None`
+    /// if the complement is too big, or too slow, to be worth having.
+    pub fn complement(&self) -> Option<Cover> {
+        let mut budget = RECURSION_BUDGET;
+        self.complement_within(&mut budget)
+    }
+
+    fn complement_within(&self, budget: &mut usize) -> Option<Cover> {
+        *budget = budget.checked_sub(1)?;
+        if self.cubes.is_empty() {
+            return Some(Cover::tautology(self.width));
+        }
+        if self
+            .cubes
+            .iter()
+            .any(|c| c.iter().all(|l| *l == Literal::DontCare))
+        {
+            return Some(Cover::empty(self.width));
+        }
+        if let [cube] = &self.cubes[..] {
+            // De Morgan: one cube per literal, with that literal inverted.
+            let cubes = cube
+                .iter()
+                .enumerate()
+                .filter(|(_, l)| **l != Literal::DontCare)
+                .map(|(v, l)| {
+                    let mut c = vec![Literal::DontCare; self.width];
+                    c[v] = l.invert();
+                    c
+                })
+                .collect();
+            return Some(Cover {
+                width: self.width,
+                cubes,
+            });
+        }
+        let var = self
+            .split_variable()
+            .expect("a cover without a tautology cube uses at least one variable");
+        let mut res = Cover::empty(self.width);
+        for (value, lit) in [(true, Literal::One), (false, Literal::Zero)] {
+            for mut c in self.cofactor(var, value).complement_within(budget)?.cubes {
+                c[var] = lit;
+                res.cubes.push(c);
+            }
+        }
+        if res.cubes.len() > MAX_COMPLEMENT_CUBES {
+            return None;
+        }
+        res.merge_adjacent();
+        Some(res)
+    }
+}
+
+impl Cover {
+    /// Number of products, then number of literals. Lower is better.
+    fn cost(&self) -> (usize, usize) {
+        let literals = self
+            .cubes
+            .iter()
+            .flatten()
+            .filter(|l| **l != Literal::DontCare)
+            .count();
+        (self.cubes.len(), literals)
+    }
+
+    /// Returns true if the cover is true for every input, or `None` if that takes too long
+    /// to find out.
+    fn is_tautology(&self, budget: &mut usize) -> Option<bool> {
+        *budget = budget.checked_sub(1)?;
+        if self
+            .cubes
+            .iter()
+            .any(|c| c.iter().all(|l| *l == Literal::DontCare))
+        {
+            return Some(true);
+        }
+        let Some(var) = self.split_variable() else {
+            return Some(false);
+        };
+        Some(
+            self.cofactor(var, true).is_tautology(budget)?
+                && self.cofactor(var, false).is_tautology(budget)?,
+        )
+    }
+
+    /// Returns true if every minterm of `cube` is covered by this cover, or `None` if that
+    /// takes too long to find out.
+    fn covers(&self, cube: &Cube) -> Option<bool> {
+        // cofactor against the cube, then check that what's left is always true.
+        let cubes = self
+            .cubes
+            .iter()
+            .filter(|c| cube_intersects(c, cube))
+            .map(|c| {
+                c.iter()
+                    .zip(cube)
+                    .map(|(l, fixed)| {
+                        if *fixed == Literal::DontCare {
+                            *l
+                        } else {
+                            Literal::DontCare
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut budget = RECURSION_BUDGET;
+        Cover {
+            width: self.width,
+            cubes,
+        }
+        .is_tautology(&mut budget)
+    }
+
+    /// Raise literals to don't-care wherever the cube stays clear of the off-set.
+    fn expand(&mut self, off: &Cover) {
+        // expand the most specific cubes first, they have the most to gain.
+        self.cubes
+            .sort_by_key(|c| c.iter().filter(|l| **l == Literal::DontCare).count());
+        for cube in self.cubes.iter_mut() {
+            for var in 0..self.width {
+                if cube[var] == Literal::DontCare {
+                    continue;
+                }
+                let lit = cube[var];
+                cube[var] = Literal::DontCare;
+                if off.cubes.iter().any(|o| cube_intersects(o, cube)) {
+                    cube[var] = lit;
+                }
+            }
+        }
+        self.remove_contained();
+    }
+
+    /// Drop cubes that are known to be covered by the rest of the cover.
+    fn irredundant(&mut self) {
+        // try to drop the most specific cubes first.
+        self.cubes
+            .sort_by_key(|c| c.iter().filter(|l| **l == Literal::DontCare).count());
+        let mut i = 0;
+        while i < self.cubes.len() {
+            let cube = self.cubes.remove(i);
+            if self.covers(&cube) != Some(true) {
+                self.cubes.insert(i, cube);
+                i += 1;
+            }
+        }
+    }
+
+    /// Two-level minimisation: cube merging and containment removal, followed by
+    /// expand/irredundant passes against the off-set until the cover stops improving.
+    /// Covers whose off-set is too big to work out only get the first two steps.
+    pub fn minimise(&self) -> Cover {
+        let mut f = self.clone();
+        f.remove_contained();
+        f.merge_adjacent();
+        let Some(off) = self.complement() else {
+            return f;
+        };
+        loop {
+            let before = f.cost();
+            f.expand(&off);
+            f.irredundant();
+            if f.cost() >= before {
+                break;
+            }
+        }
+        f
+    }
+}
+
+impl fmt::Display for Cover {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // espresso style, one cube per line with A[0] first.
+        for c in &self.cubes {
+            let line: String = c
+                .iter()
+                .map(|l| match l {
+                    Literal::Zero => '0',
+                    Literal::One => '1',
+                    Literal::DontCare => '-',
+                })
+                .collect();
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(width: u32, table: &str) -> GalSopParameters {
+        GalSopParameters {
+            depth: table.len() as u32 / (2 * width),
+            table: table.to_string(),
+            width,
+        }
+    }
+
+    fn inputs(width: usize, n: usize) -> Vec<bool> {
+        (0..width).map(|b| n & (1 << b) != 0).collect()
+    }
+
+    #[test]
+    fn test_table_round_trip() {
+        // A[1] & !A[0], and A[0] alone
+        let p = params(2, "10010010");
+        let c = Cover::from_params(&p);
+        assert_eq!(c.cubes[0], vec![Literal::Zero, Literal::One]);
+        assert_eq!(c.cubes[1], vec![Literal::One, Literal::DontCare]);
+        assert_eq!(c.to_params(), p);
+    }
+
+    #[test]
+    fn test_complement_nor() {
+        // a wide OR has a single product complement.
+        let p = params(4, "00000010000000100000001000000010");
+        let c = Cover::from_params(&p);
+        assert_eq!(c.depth(), 4);
+        let inv = c.complement().unwrap();
+        assert_eq!(inv.depth(), 1);
+        for n in 0..16 {
+            let i = inputs(4, n);
+            assert_eq!(c.eval(&i), !inv.eval(&i));
+        }
+    }
+
+    #[test]
+    fn test_minimise() {
+        // !A0&!A1 + !A0&A1 + A0&A1 + A1&A2 == !A0 + A1
+        let p = params(3, "000101001001001010101000");
+        let c = Cover::from_params(&p);
+        let m = c.minimise();
+        assert_eq!(m.depth(), 2);
+        for n in 0..8 {
+            let i = inputs(3, n);
+            assert_eq!(c.eval(&i), m.eval(&i));
+        }
+    }
+
+    #[test]
+    fn test_minimise_keeps_minimal() {
+        // xor can't be reduced.
+        let p = params(2, "10010110");
+        let c = Cover::from_params(&p);
+        assert_eq!(c.minimise().depth(), 2);
+    }
+
+    #[test]
+    fn test_complement_xor() {
+        let p = params(3, "100101011001010110101010");
+        let c = Cover::from_params(&p);
+        let inv = c.complement().unwrap();
+        let again = inv.complement().unwrap();
+        for n in 0..8 {
+            let i = inputs(3, n);
+            assert_eq!(c.eval(&i), !inv.eval(&i));
+            assert_eq!(c.eval(&i), again.eval(&i));
+        }
+    }
+
+    #[test]
+    fn test_complement_budget() {
+        // a0&b0 | a1&b1 | ... over 22 inputs, whose complement has 2^11 products.
+        let cubes = (0..11)
+            .map(|i| {
+                let mut c = vec![Literal::DontCare; 22];
+                c[2 * i] = Literal::One;
+                c[2 * i + 1] = Literal::One;
+                c
+            })
+            .collect();
+        let c = Cover { width: 22, cubes };
+        assert_eq!(c.complement(), None);
+        // the minimiser gives up on it too, rather than hang.
+        assert_eq!(c.minimise(), c);
+    }
+}
@@ -0,0 +1,98 @@
+//! Pin naming and term rendering shared by the Verilog, PLD and CUPL writers.
+//!
+//! Each format only differs in how it spells constants and operators, which is described by a
+//! `Syntax`.
+
+use crate::fitter::Fit;
+use crate::yosys_parser::Graph;
+use galette::gal::Term;
+use std::collections::{BTreeMap, HashSet};
+
+/// How a source format writes constants and operators.
+pub struct Syntax {
+    pub zero: &'static str,
+    pub one: &'static str,
+    /// Written for pins that have no name.
+    pub unknown: &'static str,
+    pub not: &'static str,
+    pub and: &'static str,
+    pub or: &'static str,
+    /// Bracket anything bigger than a single literal, so it can be negated.
+    pub bracket: bool,
+}
+
+/// Render a sum of products over pin names. Each name is flagged if it holds the complement
+/// of the pin's value.
+pub fn term_expr(term: &Term, names: &BTreeMap<u32, (String, bool)>, syntax: &Syntax) -> String {
+    if term.pins.is_empty() {
+        return syntax.zero.to_string();
+    }
+    let products: Vec<String> = term
+        .pins
+        .iter()
+        .map(|product| {
+            if product.is_empty() {
+                return syntax.one.to_string();
+            }
+            let literals: Vec<String> = product
+                .iter()
+                .map(|p| {
+                    let (name, inverted) = names
+                        .get(&(p.pin as u32))
+                        .map_or((syntax.unknown, false), |(n, inv)| (n.as_str(), *inv));
+                    if p.neg != inverted {
+                        format!("{}{name}", syntax.not)
+                    } else {
+                        name.to_string()
+                    }
+                })
+                .collect();
+            literals.join(syntax.and)
+        })
+        .collect();
+    let single = term.pins.len() == 1 && term.pins[0].len() <= 1;
+    if syntax.bracket && !single {
+        format!("({})", products.join(syntax.or))
+    } else {
+        products.join(syntax.or)
+    }
+}
+
+/// Whether the term has a product that is always true.
+pub fn is_true(term: &Term) -> bool {
+    term.pins.iter().any(|p| p.is_empty())
+}
+
+/// Name every used pin after its signal, passed through `mangle`. Clashes get a number added,
+/// names in `taken` are never used, and with `fold_case` names that only differ in case clash.
+pub fn pin_names(
+    fit: &Fit,
+    graph: &Graph,
+    mangle: impl Fn(&str) -> String,
+    taken: &[&str],
+    fold_case: bool,
+) -> BTreeMap<u32, String> {
+    let fold = |name: &str| if fold_case { name.to_lowercase() } else { name.to_string() };
+    let mut used: HashSet<String> = taken.iter().map(|n| fold(n)).collect();
+    fit.pin_signals(graph)
+        .into_iter()
+        .map(|(pin, signal)| {
+            let base = mangle(&signal);
+            let mut name = base.clone();
+            let mut n = 1;
+            while !used.insert(fold(&name)) {
+                name = format!("{base}_{n}");
+                n += 1;
+            }
+            (pin, name)
+        })
+        .collect()
+}
+
+/// Pin names as they are, none of them inverted.
+pub fn plain(names: &BTreeMap<u32, String>) -> BTreeMap<u32, (String, bool)> {
+    names
+        .iter()
+        .map(|(pin, name)| (*pin, (name.clone(), false)))
+        .collect()
+}
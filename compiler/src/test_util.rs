@@ -0,0 +1,172 @@
+//! Netlist builders and testcase loaders shared by the unit tests.
+//!
+//! Nets are numbered as yosys numbers them, and nodes are unnamed unless passed through
+//! `named`.
+
+use crate::fitter::{chip_to_olmc_offset, Fit};
+use crate::pcf::{parse_pcf, PcfFile};
+use crate::yosys_parser::{
+    GALOLMCParameters, GalInput, GalOLMC, GalSop, GalSopParameters, Graph, NamedPort, Net, Node,
+    PortDirection, YosysDoc,
+};
+use galette::blueprint::{Blueprint, PinMode};
+use galette::chips::Chip;
+use galette::gal::{Pin, Term};
+use std::collections::HashMap;
+
+/// Netlists in `testcases/hand`, with the pin constraints in the repo's `testcases` for the
+/// designs they stand in for.
+pub const TESTCASES: [(&str, &str); 3] = [
+    ("and_gate", "and_gate"),
+    ("tristate", "tristate"),
+    ("up_counter_downto", "up_counter"),
+];
+
+pub fn conns(list: &[(&str, Vec<Net>)]) -> HashMap<String, Vec<Net>> {
+    list.iter()
+        .map(|(k, v)| (k.to_string(), v.clone()))
+        .collect()
+}
+
+/// A SOP of nets `a` driving `y`, with the product count taken from the table.
+pub fn sop(a: &[u32], y: u32, table: &str) -> Node {
+    let width = a.len() as u32;
+    Node::Sop(GalSop {
+        name: None,
+        connections: conns(&[
+            ("A", a.iter().map(|n| Net::N(*n)).collect()),
+            ("Y", vec![Net::N(y)]),
+        ]),
+        parameters: GalSopParameters {
+            depth: table.len() as u32 / (2 * width),
+            width,
+            table: table.to_string(),
+        },
+    })
+}
+
+pub fn input(a: u32, y: u32) -> Node {
+    Node::Input(GalInput {
+        name: None,
+        connections: conns(&[("A", vec![Net::N(a)]), ("Y", vec![Net::N(y)])]),
+    })
+}
+
+/// An OLMC driving `y` from `a`, enabled by `e`. Registered OLMCs are clocked by net 1.
+pub fn olmc(a: u32, e: Net, y: u32, registered: bool) -> Node {
+    Node::Olmc(GalOLMC {
+        name: None,
+        parameters: GALOLMCParameters {
+            inverted: false,
+            registered,
+        },
+        connections: conns(&[
+            ("A", vec![Net::N(a)]),
+            ("C", vec![if registered { Net::N(1) } else { Net::NotConnected }]),
+            ("E", vec![e]),
+            ("Y", vec![Net::N(y)]),
+        ]),
+    })
+}
+
+pub fn named(mut node: Node, name: &str) -> Node {
+    match &mut node {
+        Node::Olmc(o) => o.name = Some(name.to_string()),
+        Node::Input(i) => i.name = Some(name.to_string()),
+        Node::Sop(s) => s.name = Some(name.to_string()),
+    }
+    node
+}
+
+/// An OLMC with its output inverted.
+pub fn inverted(mut node: Node) -> Node {
+    if let Node::Olmc(o) = &mut node {
+        o.parameters.inverted = true;
+    }
+    node
+}
+
+/// Add ports, naming their nets after them.
+pub fn ports(g: &mut Graph, list: &[(&str, u32, PortDirection)]) {
+    for (name, net, direction) in list {
+        g.ports.push(NamedPort {
+            name: name.to_string(),
+            net: Net::N(*net),
+            direction: direction.clone(),
+        });
+        g.netnames.insert(Net::N(*net), name.to_string());
+    }
+}
+
+/// A fit for the source writers: `q` is a register and `y` an active low tristate output,
+/// both loading `in[0] & !in[1] | q`, with `y` enabled by `in[1]`. They sit on the first two
+/// rows of the 22V10 and the last two of the 16V8.
+pub fn writer_fit(chip: Chip) -> (Fit, Graph) {
+    let mut g = Graph::default();
+    ports(
+        &mut g,
+        &[
+            ("clk", 1, PortDirection::Input),
+            ("in[0]", 2, PortDirection::Input),
+            ("in[1]", 3, PortDirection::Input),
+            ("q", 4, PortDirection::Output),
+            ("y", 5, PortDirection::Output),
+        ],
+    );
+    let (q, y) = match chip {
+        Chip::GAL22V10 => (1, 0),
+        _ => (chip.num_olmcs() - 1, chip.num_olmcs() - 2),
+    };
+    let offset = chip_to_olmc_offset(&chip);
+    let pcf = parse_pcf(&format!(
+        "set_io clk 1\nset_io in[0] 2\nset_io in[1] 3\nset_io q {}\nset_io y {}",
+        q + offset,
+        y + offset
+    ));
+    let mut bp = Blueprint::new(chip);
+    let term = Term {
+        line_num: 0,
+        pins: vec![
+            vec![Pin { pin: 2, neg: false }, Pin { pin: 3, neg: true }],
+            vec![Pin { pin: q + offset, neg: false }],
+        ],
+    };
+    let en = Term {
+        line_num: 0,
+        pins: vec![vec![Pin { pin: 3, neg: false }]],
+    };
+    bp.olmcs[q]
+        .set_base(&Pin { pin: 0, neg: false }, term.clone(), PinMode::Registered)
+        .unwrap();
+    bp.olmcs[y]
+        .set_base(&Pin { pin: 0, neg: true }, term, PinMode::Tristate)
+        .unwrap();
+    bp.olmcs[y].set_enable(&Pin { pin: 0, neg: false }, en).unwrap();
+    let fit = Fit {
+        chip,
+        blueprint: bp,
+        olmcmap: vec![None; chip.num_olmcs()],
+        pcf,
+    };
+    (fit, g)
+}
+
+/// A netlist from `testcases/json`.
+pub fn testcase(netlist: &str) -> Graph {
+    load(&format!("testcases/json/{netlist}.json"))
+}
+
+/// A hand-written netlist from `testcases/hand`.
+pub fn hand_netlist(netlist: &str) -> Graph {
+    load(&format!("testcases/hand/{netlist}.json"))
+}
+
+fn load(path: &str) -> Graph {
+    let f = std::fs::read(path).unwrap();
+    Graph::from(serde_json::from_slice::<YosysDoc>(&f).unwrap())
+}
+
+/// Pin constraints from the repo's `testcases`.
+pub fn testcase_pcf(name: &str) -> PcfFile {
+    parse_pcf(&std::fs::read_to_string(format!("../testcases/{name}.pcf")).unwrap())
+}
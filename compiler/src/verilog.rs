@@ -0,0 +1,213 @@
+//! Write a fitted design back out as structural Verilog.
+//!
+//! The module is generated from the blueprint, the same data the JEDEC writer uses, so it
+//! simulates what the chip will really do: one `assign` per OLMC row with the programmed
+//! product terms, output polarity and output enable, plus a register for registered rows.
+
+use crate::fitter::{chip_to_olmc_offset, feedback_inverted, Fit};
+use crate::terms::{is_true, term_expr, Syntax};
+use crate::yosys_parser::{group_bits, Graph, PortDirection};
+use galette::blueprint::{Active, PinMode};
+use galette::chips::Chip;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+/// Escape a name that isn't a plain Verilog identifier.
+fn ident(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if plain {
+        name.to_string()
+    } else {
+        format!("\\{name} ")
+    }
+}
+
+/// Sums of products over pin signals, bracketed so they can be negated.
+const SYNTAX: Syntax = Syntax {
+    zero: "1'b0",
+    one: "1'b1",
+    unknown: "1'bx",
+    not: "~",
+    and: " & ",
+    or: " | ",
+    bracket: true,
+};
+
+/// Generate a Verilog module equivalent to the fitted design.
+pub fn to_verilog(fit: &Fit, graph: &Graph) -> String {
+    let chip = fit.chip;
+    let signals = fit.pin_signals(graph);
+
+    // declare the ports as the vectors they came from, and refer to bits with a bit-select.
+    let ports = group_bits(
+        graph
+            .ports
+            .iter()
+            .map(|p| (p.name.clone(), p.direction.clone()))
+            .collect(),
+    );
+    let mut exprs: HashMap<String, String> = HashMap::new();
+    let mut decls = Vec::new();
+    for (name, bits) in &ports {
+        let dir = match bits[0] {
+            PortDirection::Input => "input",
+            PortDirection::Output => "output",
+            PortDirection::Inout => "inout",
+        };
+        if bits.len() == 1 && graph.ports.iter().any(|p| &p.name == name) {
+            exprs.insert(name.clone(), ident(name));
+            decls.push(format!("{dir} wire {}", ident(name)));
+        } else {
+            for i in 0..bits.len() {
+                exprs.insert(format!("{name}[{i}]"), format!("{}[{i}]", ident(name)));
+            }
+            let range = if graph.upto.contains(name) {
+                format!("[0:{}]", bits.len() - 1)
+            } else {
+                format!("[{}:0]", bits.len() - 1)
+            };
+            decls.push(format!("{dir} wire {range} {}", ident(name)));
+        }
+    }
+    decls.sort();
+
+    let clock = match signals.get(&1) {
+        Some(name) => exprs.get(name).cloned().unwrap_or_else(|| ident(name)),
+        None => "clk".to_string(),
+    };
+    let registered = fit
+        .blueprint
+        .olmcs
+        .iter()
+        .any(|o| matches!(o.output, Some((PinMode::Registered, _))));
+    if registered && !signals.contains_key(&1) {
+        decls.push("input wire clk".to_string());
+    }
+
+    // every pin a term can read, including buried OLMC outputs.
+    let mut names: BTreeMap<u32, String> = BTreeMap::new();
+    let mut buried = Vec::new();
+    for (pin, signal) in &signals {
+        let expr = match exprs.get(signal) {
+            Some(e) => e.clone(),
+            None => {
+                buried.push(ident(signal));
+                ident(signal)
+            }
+        };
+        names.insert(*pin, expr);
+    }
+
+    // registered rows are read back from the register, whatever the pin is doing.
+    let offset = chip_to_olmc_offset(&chip);
+    let mut feedback: BTreeMap<u32, (String, bool)> = names
+        .iter()
+        .map(|(pin, name)| (*pin, (name.clone(), false)))
+        .collect();
+    let mut regs = Vec::new();
+    for (row, olmc) in fit.blueprint.olmcs.iter().enumerate() {
+        let pin = (row + offset) as u32;
+        if !matches!(olmc.output, Some((PinMode::Registered, _))) || !names.contains_key(&pin) {
+            continue;
+        }
+        let q = format!("q{pin}");
+        let inverted = olmc.active == Active::Low;
+        feedback.insert(pin, (q.clone(), inverted != feedback_inverted(chip, true, inverted)));
+        regs.push(q);
+    }
+
+    let mut out = String::new();
+    writeln!(out, "// Generated by ver2gal from the fitted {} design.", chip.name()).unwrap();
+    writeln!(out, "module {} (", ident(&graph.module_name())).unwrap();
+    writeln!(out, "    {}", decls.join(",\n    ")).unwrap();
+    writeln!(out, ");").unwrap();
+    for wire in &buried {
+        writeln!(out, "    wire {wire};").unwrap();
+    }
+    for q in &regs {
+        writeln!(out, "    reg {q};").unwrap();
+    }
+
+    // the GAL16V8's registered outputs are enabled by pin 11 being low, when the design uses it.
+    let oe = names.get(&11).filter(|_| chip == Chip::GAL16V8);
+    for (row, olmc) in fit.blueprint.olmcs.iter().enumerate() {
+        let Some((mode, term)) = &olmc.output else {
+            continue;
+        };
+        let pin = (row + offset) as u32;
+        let Some(name) = names.get(&pin) else {
+            continue;
+        };
+        let sop = term_expr(term, &feedback, &SYNTAX);
+        let invert = if olmc.active == Active::Low { "~" } else { "" };
+        writeln!(out).unwrap();
+        writeln!(out, "    // row {row}, pin {pin}").unwrap();
+        let value = if *mode == PinMode::Registered {
+            let q = format!("q{pin}");
+            writeln!(out, "    always @(posedge {clock}) {q} <= {sop};").unwrap();
+            format!("{invert}{q}")
+        } else {
+            format!("{invert}{sop}")
+        };
+        match (&olmc.tri_con, oe) {
+            (_, Some(oe)) if *mode == PinMode::Registered => {
+                writeln!(out, "    assign {name} = ~{oe} ? {value} : 1'bz;").unwrap()
+            }
+            (Some(en), _) if !is_true(en) => {
+                let en = term_expr(en, &feedback, &SYNTAX);
+                writeln!(out, "    assign {name} = {en} ? {value} : 1'bz;").unwrap()
+            }
+            _ => writeln!(out, "    assign {name} = {value};").unwrap(),
+        }
+    }
+    writeln!(out, "endmodule").unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pcf::parse_pcf;
+    use crate::test_util::{ports, writer_fit};
+    use galette::blueprint::Blueprint;
+    use galette::chips::Chip;
+    use galette::gal::{Pin, Term};
+
+    #[test]
+    fn test_to_verilog() {
+        let (fit, g) = writer_fit(Chip::GAL16V8);
+        let v = to_verilog(&fit, &g);
+        assert!(v.contains("input wire [1:0] in"), "{v}");
+        // the register is read back directly, not through the pin.
+        assert!(v.contains("reg q19;"), "{v}");
+        assert!(v.contains("always @(posedge clk) q19 <= (in[0] & ~in[1] | q19);"), "{v}");
+        assert!(v.contains("assign q = q19;"), "{v}");
+        assert!(v.contains("assign y = in[1] ? ~(in[0] & ~in[1] | q19) : 1'bz;"), "{v}");
+    }
+
+    #[test]
+    fn test_inverted_feedback() {
+        let mut g = Graph::default();
+        ports(&mut g, &[("q", 2, PortDirection::Output)]);
+        let pcf = parse_pcf("set_io q 19");
+        let mut bp = Blueprint::new(Chip::GAL16V8);
+        // an active low register holds the complement of its pin, and toggles.
+        let term = Term {
+            line_num: 0,
+            pins: vec![vec![Pin { pin: 19, neg: true }]],
+        };
+        bp.olmcs[7]
+            .set_base(&Pin { pin: 0, neg: true }, term, PinMode::Registered)
+            .unwrap();
+        let fit = Fit {
+            chip: Chip::GAL16V8,
+            blueprint: bp,
+            olmcmap: vec![None; 8],
+            pcf,
+        };
+        let v = to_verilog(&fit, &g);
+        assert!(v.contains("always @(posedge clk) q19 <= q19;"), "{v}");
+        assert!(v.contains("assign q = ~q19;"), "{v}");
+    }
+}
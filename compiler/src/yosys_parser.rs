@@ -1,11 +1,11 @@
+use crate::fitter::dedicated_inputs;
 use crate::pcf::PcfFile;
-use galette::gal::Pin;
+use galette::chips::Chip;
 use log::info;
-use serde::{de::Error, Deserialize, Deserializer, Serialize};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
 use serde_with::{serde_as, BoolFromInt};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fmt;
-use std::str;
 
 #[derive(Debug, Serialize, Clone, Deserialize, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Net {
@@ -22,6 +22,7 @@ pub enum Net {
 /// The GAL_INPUT marks an external ipnut
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct GalInput {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
     pub connections: HashMap<String, Vec<Net>>,
 }
@@ -44,19 +45,33 @@ where
     let val = u32::from_str_radix(s.as_str(), 2).map_err(D::Error::custom)?;
     Ok(val == 1)
 }
+// and the reverse, so yosys can read the parameters back in.
+fn to_binstr<S>(val: &u32, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&format!("{val:032b}"))
+}
+fn bool_to_binstr<S>(val: &bool, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(if *val { "1" } else { "0" })
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "UPPERCASE")]
 pub struct GalSopParameters {
-    #[serde(deserialize_with = "from_binstr")]
+    #[serde(deserialize_with = "from_binstr", serialize_with = "to_binstr")]
     pub depth: u32,
     pub table: String,
-    #[serde(deserialize_with = "from_binstr")]
+    #[serde(deserialize_with = "from_binstr", serialize_with = "to_binstr")]
     pub width: u32,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct GalSop {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
     pub connections: HashMap<String, Vec<Net>>,
     pub parameters: GalSopParameters,
@@ -66,14 +81,15 @@ pub struct GalSop {
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "UPPERCASE")]
 pub struct GALOLMCParameters {
-    #[serde(deserialize_with = "bool_from_binstr")]
+    #[serde(deserialize_with = "bool_from_binstr", serialize_with = "bool_to_binstr")]
     pub inverted: bool,
-    #[serde(deserialize_with = "bool_from_binstr")]
+    #[serde(deserialize_with = "bool_from_binstr", serialize_with = "bool_to_binstr")]
     pub registered: bool,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct GalOLMC {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
     pub parameters: GALOLMCParameters,
     pub connections: HashMap<String, Vec<Net>>,
@@ -83,8 +99,10 @@ pub struct GalOLMC {
 #[serde(deny_unknown_fields)]
 #[serde(tag = "type")]
 pub enum YosysCell {
-    #[serde(rename = "GAL_SOP", alias = "GAL_1SOP")]
+    #[serde(rename = "GAL_SOP")]
     Sop(GalSop),
+    #[serde(rename = "GAL_1SOP")]
+    OneSop(GalSop),
     #[serde(rename = "GAL_INPUT")]
     Input(GalInput),
     #[serde(rename = "GAL_OLMC")]
@@ -108,10 +126,26 @@ pub enum Port {
     InOut(ModPort),
 }
 
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NetName {
+    #[serde(default)]
+    #[serde_as(as = "BoolFromInt")]
+    pub hide_name: bool,
+    pub bits: Vec<Net>,
+    #[serde(default)]
+    #[serde_as(as = "BoolFromInt")]
+    pub upto: bool,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub attributes: HashMap<String, String>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Module {
     pub ports: HashMap<String, Port>,
     pub cells: HashMap<String, YosysCell>,
+    #[serde(default)]
+    pub netnames: HashMap<String, NetName>,
 }
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct YosysDoc {
@@ -119,60 +153,6 @@ pub struct YosysDoc {
     pub modules: HashMap<String, Module>,
 }
 
-impl GalSop {
-    // extract the logic table using the parameters.
-    // pub fn parse_table(self) -> Vec<Vec<Pin>> {
-    //     // get the list of inputs which is a Vec<u32>
-    //     let table = self.parameters.table.as_bytes();
-    //
-    //     // split into products
-    //     let product_count = self.parameters.depth;
-    //     let product_size = self.parameters.width;
-    //     // we need to have enough elements.
-    //     assert!(table.len() == (product_count * product_size * 2) as usize);
-    //
-    //     let chunksize = product_size * 2;
-    //
-    //     table
-    //         .chunks(chunksize as usize)
-    //         .map(|prod| {
-    //             let mut term = Vec::new();
-    //             for i in 0..product_size {
-    //                 let i = i as usize;
-    //                 let pin_num = self.connections.inputs.get(i).unwrap(); // this should never panic.
-    //                 let pin = table_seg_to_pin(pin_num, &prod[2 * i..2 * i + 1]);
-    //                 if let Some(p) = pin {
-    //                     term.push(p);
-    //                 }
-    //             }
-    //             // now that we've accumulated all of the products, add it to the products list.
-    //             term
-    //         })
-    //         .collect()
-    // }
-}
-
-// table_seg_to_pin reads the small chunk for a specific input to a
-// sop and creates a gal pin for it.
-fn table_seg_to_pin(num: &Net, seg: &[u8]) -> Option<Pin> {
-    let Net::N(p) = num else {
-        todo!("specials");
-    };
-    let pin_num: usize = *p as usize;
-    // convert to string for dumb reasons.
-    match str::from_utf8(seg).unwrap() {
-        "01" => Some(Pin {
-            pin: pin_num,
-            neg: true,
-        }), // negated
-        "10" => Some(Pin {
-            pin: pin_num,
-            neg: false,
-        }), // normal
-        _ => None,
-    }
-}
-
 /* constraint mapping pipeline
  * w
  * take yosys document -> look at top-level module ports
@@ -214,7 +194,10 @@ impl NamedPort {
                 .bits
                 .iter()
                 .enumerate()
-                .map(|(idx, n)| NamedPort::new(&format!("{base_name}[{idx}]"), n, &dir))
+                .map(|(idx, n)| {
+                    let idx = bit_index(idx, port.bits.len(), port.upto);
+                    NamedPort::new(&format!("{base_name}[{idx}]"), n, &dir)
+                })
                 .collect(),
             Ordering::Equal => {
                 vec![NamedPort::new(base_name, &port.bits[0], &dir)]
@@ -230,6 +213,16 @@ impl NamedPort {
     }
 }
 
+/// The source index of bit `idx` of a `width` bit vector. Yosys lists bits LSB first, which
+/// for an `upto` vector like `[0:7]` is the highest index.
+fn bit_index(idx: usize, width: usize, upto: bool) -> usize {
+    if upto {
+        width - 1 - idx
+    } else {
+        idx
+    }
+}
+
 /// NodeIdx is an index into the node list to reference a specific node.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct NodeIdx(pub usize);
@@ -286,10 +279,12 @@ impl NetAdjPair {
     pub fn uses_nodeport(&self, idx: &NodeIdx, port: &str) -> bool {
         (&self.idx1 == idx && self.port1 == port) || (&self.idx2 == idx && self.port2 == port)
     }
-    pub fn get_other(&self, my_idx: &NodeIdx) -> Option<(NodeIdx, &str)> {
-        if my_idx == &self.idx1 {
+    /// The other end of the pair, seen from the given node and port. A pair between two ports
+    /// of the same node is looked up from the side named by `my_port`.
+    pub fn get_other(&self, my_idx: &NodeIdx, my_port: &str) -> Option<(NodeIdx, &str)> {
+        if my_idx == &self.idx1 && self.port1 == my_port {
             Some((self.idx2, &self.port2))
-        } else if my_idx == &self.idx2 {
+        } else if my_idx == &self.idx2 && self.port2 == my_port {
             Some((self.idx1, &self.port1))
         } else {
             None
@@ -315,16 +310,31 @@ impl Node {
      */
     // Returns the hashmap of String (connection name) to a list of nets.
     pub fn get_connections(&self) -> HashMap<String, Vec<Net>> {
+        self.connections().clone()
+    }
+
+    /// Borrow the connections of this node.
+    pub fn connections(&self) -> &HashMap<String, Vec<Net>> {
         match self {
-            Self::Olmc(ol) => ol.connections.clone(),
-            Self::Input(i) => i.connections.clone(),
-            Self::Sop(s) => s.connections.clone(),
+            Self::Olmc(ol) => &ol.connections,
+            Self::Input(i) => &i.connections,
+            Self::Sop(s) => &s.connections,
+        }
+    }
+
+    /// The direction of a connection, seen from the cell.
+    /// OLMC outputs are `Inout`, since they drive the pin but can also be read back from it.
+    pub fn port_direction(&self, port: &str) -> PortDirection {
+        match (self, port) {
+            (Self::Olmc(_), "Y") => PortDirection::Inout,
+            (Self::Input(_) | Self::Sop(_), "Y") => PortDirection::Output,
+            _ => PortDirection::Input,
         }
     }
 
     /// Returns the connection that contains this net, if any.
     pub fn port_for_net(&self, net: &Net) -> Option<String> {
-        for (port, nets) in self.get_connections() {
+        for (port, nets) in self.connections() {
             if nets.contains(net) {
                 return Some(port.to_string());
             }
@@ -334,10 +344,21 @@ impl Node {
 
     /// Get every net that this node uses.
     pub fn get_nets(&self) -> Vec<Net> {
-        self.get_connections()
-            .iter()
-            .flat_map(|(_, nets)| nets.clone())
-            .collect()
+        self.connections().values().flatten().cloned().collect()
+    }
+
+    /// The cell name, if it has one.
+    pub fn name_ref(&self) -> Option<&str> {
+        match self {
+            Self::Olmc(ol) => ol.name.as_deref(),
+            Self::Input(i) => i.name.as_deref(),
+            Self::Sop(s) => s.name.as_deref(),
+        }
+    }
+
+    /// The cell name, for messages.
+    pub fn name(&self) -> String {
+        self.name_ref().unwrap_or("unnamed").to_string()
     }
 
     fn set_name(&mut self, n: &str) {
@@ -349,64 +370,175 @@ impl Node {
     }
 }
 
-#[derive(Default, Debug)]
+/// One bit of a node's connection that uses a given net.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetConn {
+    pub node: NodeIdx,
+    pub port: String,
+    pub bit: usize,
+    pub direction: PortDirection,
+}
+
+impl NetConn {
+    /// Returns true if the node drives the net through this connection.
+    pub fn is_driver(&self) -> bool {
+        self.direction != PortDirection::Input
+    }
+}
+
+#[derive(Default, Debug, Clone)]
 pub struct Graph {
     pub nodelist: Vec<Node>,
     pub adjlist: Vec<NetAdjPair>,
     pub ports: Vec<NamedPort>,
+    /// Every node connection on each net, built by `generate_adjacency`.
+    pub netindex: HashMap<Net, Vec<NetConn>>,
+    /// Indices into `adjlist` for each node.
+    node_adj: Vec<Vec<usize>>,
+    /// Human readable names for nets, from the yosys `netnames`.
+    pub netnames: HashMap<Net, String>,
+    /// Power-up values from yosys `init` attributes.
+    pub inits: HashMap<Net, bool>,
+    /// Multi-bit ports and wires declared `upto`, by base name.
+    pub upto: BTreeSet<String>,
+    /// Names of the SOP cells read from GAL_1SOP cells.
+    pub one_sops: BTreeSet<String>,
 }
 
-// For each node, for each port, for each net
-// find all other nodes that have this net.
-
 impl Graph {
-    /// re-generate the adjacency set for this graph.
+    /// re-generate the net index and adjacency set for this graph.
+    ///
+    /// The index is built in a single pass over the node connections, and the adjacency set
+    /// pairs up the distinct node/port combinations on each net. As before, every pair is
+    /// stored once with the lower node index first, and each node/port also gets a pair with
+    /// itself.
     pub fn generate_adjacency(&mut self) {
-        self.adjlist.clear();
-        for (idx1, node1) in self.nodelist.iter().enumerate() {
-            for net in node1.get_nets() {
-                if !matches!(net, Net::N(_)) {
-                    info!("skipping global nets");
-                    continue;
+        self.netindex.clear();
+        for (idx, node) in self.nodelist.iter().enumerate() {
+            for (port, nets) in node.connections() {
+                for (bit, net) in nets.iter().enumerate() {
+                    self.netindex.entry(net.clone()).or_default().push(NetConn {
+                        node: NodeIdx(idx),
+                        port: port.clone(),
+                        bit,
+                        direction: node.port_direction(port),
+                    });
                 }
-                let connected_nodes: Vec<_> = self
-                    .nodelist
-                    .iter()
-                    .enumerate()
-                    .filter(|&(idx2, node2)| {
-                        // if idx1 == idx2 {
-                        //     return false;
-                        // }
-                        node2.get_nets().contains(&net)
-                    })
-                    .collect();
+            }
+        }
 
-                for (idx2, node2) in connected_nodes {
-                    // get the port for node1
-                    let node1_port = node1.port_for_net(&net).expect("how");
-                    let node2_port = node2.port_for_net(&net).expect("how");
-                    let adj = NetAdjPair {
+        self.adjlist.clear();
+        self.node_adj = vec![Vec::new(); self.nodelist.len()];
+        for (net, conns) in &self.netindex {
+            if !matches!(net, Net::N(_)) {
+                info!("skipping global nets");
+                continue;
+            }
+            // a node may use the same net on several bits of a port, only pair it up once.
+            let mut ends: Vec<(NodeIdx, &str)> = Vec::new();
+            for c in conns {
+                if !ends.contains(&(c.node, c.port.as_str())) {
+                    ends.push((c.node, c.port.as_str()));
+                }
+            }
+            for (i, (idx1, port1)) in ends.iter().enumerate() {
+                for (idx2, port2) in &ends[i..] {
+                    self.adjlist.push(NetAdjPair {
                         net: net.clone(),
-                        idx1: NodeIdx(idx1),
-                        idx2: NodeIdx(idx2),
-                        port1: node1_port,
-                        port2: node2_port,
-                    };
-                    if !self.adjlist.contains(&adj) {
-                        // if this fails, it means that the opposing pair is already present.
-                        self.adjlist.push(adj);
-                    }
+                        idx1: *idx1,
+                        port1: port1.to_string(),
+                        port2: port2.to_string(),
+                        idx2: *idx2,
+                    });
                 }
             }
         }
+        // the net index is a hash map, so sort to keep the order stable between runs.
+        self.adjlist.sort_by(|a, b| {
+            (a.idx1, a.idx2, &a.net, &a.port1, &a.port2)
+                .cmp(&(b.idx1, b.idx2, &b.net, &b.port1, &b.port2))
+        });
+        for (i, adj) in self.adjlist.iter().enumerate() {
+            self.node_adj[adj.idx1.0].push(i);
+            if adj.idx1 != adj.idx2 {
+                self.node_adj[adj.idx2.0].push(i);
+            }
+        }
     }
+
+    /// Every node connection on the net.
+    pub fn net_conns(&self, net: &Net) -> &[NetConn] {
+        self.netindex.get(net).map_or(&[], |v| v.as_slice())
+    }
+
+    /// The node connections driving this net.
+    pub fn drivers(&self, net: &Net) -> Vec<&NetConn> {
+        self.net_conns(net).iter().filter(|c| c.is_driver()).collect()
+    }
+
+    /// The node connections reading this net.
+    pub fn sinks(&self, net: &Net) -> Vec<&NetConn> {
+        self.net_conns(net).iter().filter(|c| !c.is_driver()).collect()
+    }
+
+    /// The name of a net for messages, falling back to the net number.
+    pub fn net_name(&self, net: &Net) -> String {
+        match (self.netnames.get(net), net) {
+            (Some(name), _) => name.clone(),
+            (None, Net::N(n)) => format!("<net {n}>"),
+            (None, other) => format!("{other:?}"),
+        }
+    }
+
+    /// Check every net has exactly one driver and at least one reader, including module ports.
+    fn check_nets(&self) -> Vec<ValidationError> {
+        let mut problems = Vec::new();
+        let mut nets: Vec<&Net> = self.netindex.keys().filter(|n| matches!(n, Net::N(_))).collect();
+        nets.sort();
+        for net in nets {
+            let port = self.find_port(net);
+            let drivers = self.drivers(net).len();
+            // an inout port may be driven from outside too, but that's not a conflict.
+            let external_driver = port.is_some_and(|p| p.direction != PortDirection::Output);
+            let external_sink = port.is_some_and(|p| p.direction != PortDirection::Input);
+            let problem = |message: &str| ValidationError {
+                name: format!("net {}", self.net_name(net)),
+                message: message.to_string(),
+            };
+            if drivers + usize::from(port.is_some_and(|p| p.direction == PortDirection::Input)) > 1
+            {
+                problems.push(problem("has multiple drivers"));
+            }
+            if drivers == 0 && !external_driver {
+                problems.push(problem("is undriven"));
+            }
+            if self.sinks(net).is_empty() && !external_sink {
+                problems.push(problem("is dangling"));
+            }
+        }
+        problems
+    }
+
+    /// The yosys module the cells came from, i.e. whichever name prefix most of them share.
+    pub fn module_name(&self) -> String {
+        let mut prefixes: HashMap<&str, usize> = HashMap::new();
+        for node in &self.nodelist {
+            if let Some((prefix, _)) = node.name_ref().and_then(|n| n.split_once('/')) {
+                *prefixes.entry(prefix).or_default() += 1;
+            }
+        }
+        prefixes
+            .into_iter()
+            .max_by_key(|(p, count)| (*count, std::cmp::Reverse(*p)))
+            .map_or("top".to_string(), |(p, _)| p.to_string())
+    }
+
     /// Find all nodes that are attached to this net in any way.
-    /// Note that this is an expensive operation since it can't currently use the adjlist.
     pub fn find_nodes_on_net(&self, net: &Net) -> Vec<NodeIdx> {
-        let mut res = Vec::new();
-        for (idx, node) in self.nodelist.iter().enumerate() {
-            if node.get_nets().contains(net) {
-                res.push(NodeIdx(idx));
+        let mut res: Vec<NodeIdx> = Vec::new();
+        for c in self.net_conns(net) {
+            if !res.contains(&c.node) {
+                res.push(c.node);
             }
         }
         res
@@ -420,8 +552,11 @@ impl Graph {
 
     // find the connections from the given node/port ONLY WORKS FOR NON_PORT DEVICES.
     pub fn get_node_port_conns(&self, nodeidx: &NodeIdx, port: &str) -> Vec<&NetAdjPair> {
-        self.adjlist
-            .iter()
+        self.node_adj
+            .get(nodeidx.0)
+            .into_iter()
+            .flatten()
+            .map(|i| &self.adjlist[*i])
             .filter(|adj| adj.uses_nodeport(nodeidx, port))
             .collect()
     }
@@ -458,47 +593,275 @@ impl Graph {
             .collect()
     }
 
+    /// Check the clock connection of every OLMC, and that they all share one clock.
+    fn check_clocks(&self) -> Vec<ValidationError> {
+        let mut problems = Vec::new();
+        let mut clock: Option<&Net> = None;
+        for node in &self.nodelist {
+            let Node::Olmc(o) = node else {
+                continue;
+            };
+            let Some(c) = o.connections.get("C") else {
+                continue;
+            };
+            let name = node.name();
+            match &c[..] {
+                [net @ Net::N(_)] => match clock {
+                    None => clock = Some(net),
+                    Some(clk) if clk != net => problems.push(ValidationError::new(
+                        &name,
+                        &format!(
+                            "clock {} is not shared amongst all OLMCs (expected {})",
+                            self.net_name(net),
+                            self.net_name(clk)
+                        ),
+                    )),
+                    _ => {}
+                },
+                [Net::NotConnected] => {}
+                [_] => problems.push(ValidationError::new(&name, "invalid clock pin")),
+                _ => problems.push(ValidationError::new(
+                    &name,
+                    "OLMC has more than one clock input",
+                )),
+            }
+        }
+        problems
+    }
+
+    /// Check SOP parameters against their connections.
+    fn check_sops(&self) -> Vec<ValidationError> {
+        let mut problems = Vec::new();
+        for node in &self.nodelist {
+            let Node::Sop(sop) = node else {
+                continue;
+            };
+            let name = node.name();
+            let p = &sop.parameters;
+            if p.table.len() != (2 * p.width * p.depth) as usize {
+                problems.push(ValidationError::new(
+                    &name,
+                    &format!(
+                        "TABLE has {} bits, expected 2*WIDTH*DEPTH = {}",
+                        p.table.len(),
+                        2 * p.width * p.depth
+                    ),
+                ));
+            }
+            let inputs = sop.connections.get("A").map_or(&[][..], |a| a.as_slice());
+            if inputs.len() != p.width as usize {
+                problems.push(ValidationError::new(
+                    &name,
+                    &format!("A has {} bits but WIDTH is {}", inputs.len(), p.width),
+                ));
+            }
+            // an x input is fine as long as every product ignores it.
+            let width = p.width as usize;
+            let used_x = inputs.iter().enumerate().any(|(i, net)| {
+                *net == Net::NotConnected
+                    && i < width
+                    && p.table
+                        .as_bytes()
+                        .chunks(2 * width)
+                        .filter(|prod| prod.len() == 2 * width)
+                        .any(|prod| &prod[2 * (width - 1 - i)..2 * (width - i)] != b"00")
+            });
+            if used_x {
+                problems.push(ValidationError::new(&name, "unconnected (x) net used as an input"));
+            }
+        }
+        problems
+    }
+
+    /// Check each OLMC is driven by a SOP, and its output enable SOP has a single product.
+    fn check_olmc_inputs(&self) -> Vec<ValidationError> {
+        let mut problems = Vec::new();
+        for (idx, node) in self.nodelist.iter().enumerate() {
+            let Node::Olmc(o) = node else {
+                continue;
+            };
+            let name = node.name();
+            for port in ["A", "E"] {
+                let Some([net @ Net::N(_)]) = o.connections.get(port).map(|n| &n[..]) else {
+                    if port == "A" {
+                        problems.push(ValidationError::new(&name, "A is not connected to a net"));
+                    }
+                    continue;
+                };
+                let sop = self.drivers(net).into_iter().find_map(|d| {
+                    match self.get_node(&d.node) {
+                        Some(Node::Sop(s)) if d.node != NodeIdx(idx) => Some(s),
+                        _ => None,
+                    }
+                });
+                match (port, sop) {
+                    ("A", None) => {
+                        problems.push(ValidationError::new(&name, "no SOP drives the A input"))
+                    }
+                    ("E", Some(s)) if s.parameters.depth > 1 => problems.push(ValidationError::new(
+                        s.name.as_deref().unwrap_or("unnamed"),
+                        &format!(
+                            "output enable SOP for {name} has {} products, only 1 is available",
+                            s.parameters.depth
+                        ),
+                    )),
+                    _ => {}
+                }
+            }
+        }
+        problems
+    }
+
+    /// Find loops through the SOPs and combinational OLMCs. Registered OLMCs break loops.
+    fn check_comb_loops(&self) -> Vec<ValidationError> {
+        // edges from each node to the nodes reading its outputs.
+        let succ: Vec<Vec<usize>> = self
+            .nodelist
+            .iter()
+            .map(|node| {
+                if matches!(node, Node::Olmc(o) if o.parameters.registered) {
+                    return Vec::new();
+                }
+                let mut next: Vec<usize> = node
+                    .connections()
+                    .iter()
+                    .filter(|(port, _)| node.port_direction(port) != PortDirection::Input)
+                    .flat_map(|(_, nets)| nets)
+                    .filter(|net| matches!(net, Net::N(_)))
+                    .flat_map(|net| self.sinks(net))
+                    .map(|c| c.node.0)
+                    .collect();
+                next.sort();
+                next.dedup();
+                next
+            })
+            .collect();
+
+        // iterative DFS, 0 = unvisited, 1 = on the stack, 2 = done.
+        let mut problems = Vec::new();
+        let mut state = vec![0u8; self.nodelist.len()];
+        for start in 0..self.nodelist.len() {
+            if state[start] != 0 {
+                continue;
+            }
+            let mut stack: Vec<(usize, usize)> = vec![(start, 0)];
+            state[start] = 1;
+            while let Some((node, edge)) = stack.last_mut() {
+                let node = *node;
+                if let Some(&next) = succ[node].get(*edge) {
+                    *edge += 1;
+                    match state[next] {
+                        0 => {
+                            state[next] = 1;
+                            stack.push((next, 0));
+                        }
+                        1 => {
+                            let pos = stack.iter().position(|(n, _)| *n == next).unwrap();
+                            let path: Vec<String> = stack[pos..]
+                                .iter()
+                                .map(|(n, _)| self.nodelist[*n].name())
+                                .collect();
+                            problems.push(ValidationError::new(
+                                &self.nodelist[next].name(),
+                                &format!("combinational loop: {}", path.join(" -> ")),
+                            ));
+                        }
+                        _ => {}
+                    }
+                } else {
+                    state[node] = 2;
+                    stack.pop();
+                }
+            }
+        }
+        problems
+    }
+
     /// Validate that the graph has valid invariants.
     /// This function does not guarantee a mapping, but it does mean that the output produced
     /// by the yosys script is what we expected. Mainly a tool for debugging the Yosys outputs.
-    pub fn validate(&self) -> Result<(), &str> {
+    /// Every problem found is returned, not just the first.
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
         info!("Checking OLMC blocks");
-        let olmc = self.nodelist.iter().filter_map(|node| match node {
-            Node::Olmc(o) => Some(o),
-            _ => None,
-        });
-        let olmc_clock = olmc.filter_map(|o| o.connections.get("C"));
-        let test = olmc_clock.clone().all(|v| v.len() == 1);
-        if !test {
-            return Err("OLMC has more than one clock input!");
-        }
-        // assert that all olmc C nets are either not connected or to a net
-        let test = olmc_clock
-            .clone()
-            .flatten()
-            .all(|net| matches!(net, Net::NotConnected) || matches!(net, Net::N(_)));
-        if !test {
-            return Err("invalid clock pin");
-        }
-        // for the ones connected to a net, extract the net number so we can make sure they're all
-        // the same clock.
-        let olmc_clocked: Vec<u32> = olmc_clock
-            .clone()
-            .flatten()
-            .filter_map(|net| match net {
-                Net::N(x) => Some(*x),
-                _ => None,
-            })
+        let mut problems = self.check_clocks();
+        problems.extend(self.check_olmc_inputs());
+        info!("Checking SOP blocks");
+        problems.extend(self.check_sops());
+        info!("Checking net drivers");
+        problems.extend(self.check_nets());
+        info!("Checking for combinational loops");
+        problems.extend(self.check_comb_loops());
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationErrors(problems))
+        }
+    }
+
+    /// Validate the graph, and also check it doesn't need more inputs than the chip has.
+    pub fn validate_for_chip(&self, chip: Chip) -> Result<(), ValidationErrors> {
+        let mut problems = match self.validate() {
+            Ok(()) => Vec::new(),
+            Err(ValidationErrors(p)) => p,
+        };
+        let inputs: Vec<&NamedPort> = self
+            .ports
+            .iter()
+            .filter(|p| p.direction != PortDirection::Output)
             .collect();
-        let test = olmc_clocked.windows(2).all(|w| w[0] == w[1]);
-        if !test {
-            return Err("clock pin is not shared amongst all OLMCs");
+        let clocked = self
+            .get_olmc()
+            .iter()
+            .any(|o| matches!(o.connections().get("C").map(|c| &c[..]), Some([Net::N(_)])));
+        // a clock gets its own pin, everything else shares the dedicated inputs and I/O pins.
+        let lines = dedicated_inputs(chip, clocked).len() + chip.num_olmcs() + usize::from(clocked);
+        if inputs.len() > lines {
+            problems.push(ValidationError::new(
+                chip.name(),
+                &format!(
+                    "design has {} inputs, but the chip only has {lines} input lines",
+                    inputs.len()
+                ),
+            ));
         }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationErrors(problems))
+        }
+    }
+}
 
-        Ok(())
+/// A problem found while validating the graph, with the name of the cell or net at fault.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("{name}: {message}")]
+pub struct ValidationError {
+    pub name: String,
+    pub message: String,
+}
+
+impl ValidationError {
+    fn new(name: &str, message: &str) -> Self {
+        ValidationError {
+            name: name.to_string(),
+            message: message.to_string(),
+        }
     }
 }
 
+/// Every problem found by `Graph::validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationErrors(pub Vec<ValidationError>);
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines: Vec<String> = self.0.iter().map(|e| e.to_string()).collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
 const TECHMAP_NAMES: [&str; 5] = ["DFF_P", "GAL_INPUT", "GAL_SOP", "GAL_OLMC", "GAL_1SOP"];
 
 impl From<YosysDoc> for Graph {
@@ -512,17 +875,50 @@ impl From<YosysDoc> for Graph {
             }
             for (cell_name, cell) in module.cells {
                 info!("Processing cell {}", cell_name);
+                let fully_qualified_name = format!("{mod_name}/{cell_name}");
                 let mut newcell = match cell {
                     YosysCell::Input(d) => Node::Input(d),
                     YosysCell::Sop(s) => Node::Sop(s),
+                    YosysCell::OneSop(s) => {
+                        g.one_sops.insert(fully_qualified_name.clone());
+                        Node::Sop(s)
+                    }
                     YosysCell::OLMC(n) => Node::Olmc(n),
                 };
-                let fully_qualified_name = format!("{mod_name}/{cell_name}");
                 newcell.set_name(&fully_qualified_name);
                 g.nodelist.push(newcell);
             }
+            // prefer names that yosys doesn't hide, i.e. ones from the source.
+            let mut hidden: HashMap<Net, bool> = HashMap::new();
+            for (name, netname) in &module.netnames {
+                for (idx, net) in netname.bits.iter().enumerate() {
+                    if !matches!(net, Net::N(_)) || hidden.get(net) == Some(&false) {
+                        continue;
+                    }
+                    if netname.hide_name && hidden.contains_key(net) {
+                        continue;
+                    }
+                    let bitname = if netname.bits.len() > 1 {
+                        format!("{name}[{}]", bit_index(idx, netname.bits.len(), netname.upto))
+                    } else {
+                        name.clone()
+                    };
+                    hidden.insert(net.clone(), netname.hide_name);
+                    g.netnames.insert(net.clone(), bitname);
+                }
+                if netname.upto {
+                    g.upto.insert(name.clone());
+                }
+                if let Some(init) = netname.attributes.get("init") {
+                    g.inits.extend(parse_init(init, &netname.bits));
+                }
+            }
             for (port_name, port) in module.ports {
                 info!("Processing port {}", port_name);
+                let (Port::Output(p) | Port::Input(p) | Port::InOut(p)) = &port;
+                if p.upto {
+                    g.upto.insert(port_name.clone());
+                }
                 let new_ports: Vec<NamedPort> = match port {
                     Port::Output(o) => NamedPort::new_split(&port_name, o, PortDirection::Output),
                     Port::Input(i) => NamedPort::new_split(&port_name, i, PortDirection::Input),
@@ -536,6 +932,158 @@ impl From<YosysDoc> for Graph {
     }
 }
 
+/// Read an `init` attribute, a binary string with the most significant bit first, into a
+/// value for each net. Bits that are `x` have no value.
+pub fn parse_init(init: &str, bits: &[Net]) -> Vec<(Net, bool)> {
+    init.chars()
+        .rev()
+        .zip(bits)
+        .filter(|(_, net)| matches!(net, Net::N(_)))
+        .filter_map(|(c, net)| match c {
+            '0' => Some((net.clone(), false)),
+            '1' => Some((net.clone(), true)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Split a `name[index]` bit name into its base name and index.
+pub fn split_bit_name(name: &str) -> Option<(&str, usize)> {
+    let (base, idx) = name.strip_suffix(']')?.rsplit_once('[')?;
+    Some((base, idx.parse().ok()?))
+}
+
+/// Gather single-bit names back into multi-bit vectors, the reverse of `NamedPort::new_split`.
+/// Bits are only grouped when every index from 0 up is present, otherwise they keep their own name.
+pub fn group_bits<T: Clone>(named: Vec<(String, T)>) -> Vec<(String, Vec<T>)> {
+    let mut groups: HashMap<String, Vec<(usize, String, T)>> = HashMap::new();
+    let mut out = Vec::new();
+    for (name, item) in named {
+        match split_bit_name(&name) {
+            Some((base, idx)) => groups
+                .entry(base.to_string())
+                .or_default()
+                .push((idx, name.clone(), item)),
+            None => out.push((name, vec![item])),
+        }
+    }
+    for (base, mut bits) in groups {
+        bits.sort_by_key(|(idx, _, _)| *idx);
+        let complete = bits.iter().enumerate().all(|(i, (idx, _, _))| i == *idx);
+        if complete && !out.iter().any(|(n, _)| *n == base) {
+            out.push((base, bits.into_iter().map(|(_, _, item)| item).collect()));
+        } else {
+            out.extend(bits.into_iter().map(|(_, name, item)| (name, vec![item])));
+        }
+    }
+    out
+}
+
+impl From<&Graph> for YosysDoc {
+    /// Write the graph back out as a single yosys module. Cell names have the module prefix
+    /// added by `From<YosysDoc>` stripped off again, and unnamed cells are given a fresh name.
+    /// `upto` ports and GAL_1SOP cells are written back the way they were read.
+    fn from(graph: &Graph) -> Self {
+        let mod_name = graph.module_name();
+
+        let mut cells = HashMap::new();
+        for (idx, node) in graph.nodelist.iter().enumerate() {
+            let name = match node.name_ref() {
+                Some(n) => n
+                    .strip_prefix(&format!("{mod_name}/"))
+                    .unwrap_or(n)
+                    .to_string(),
+                None => format!("$ver2gal${idx}"),
+            };
+            let cell = match node.clone() {
+                Node::Input(i) => YosysCell::Input(GalInput { name: None, ..i }),
+                Node::Sop(s) if s.name.as_ref().is_some_and(|n| graph.one_sops.contains(n)) => {
+                    YosysCell::OneSop(GalSop { name: None, ..s })
+                }
+                Node::Sop(s) => YosysCell::Sop(GalSop { name: None, ..s }),
+                Node::Olmc(o) => YosysCell::OLMC(GalOLMC { name: None, ..o }),
+            };
+            cells.insert(name, cell);
+        }
+
+        let ports = group_bits(
+            graph
+                .ports
+                .iter()
+                .map(|p| (p.name.clone(), (p.net.clone(), p.direction.clone())))
+                .collect(),
+        )
+        .into_iter()
+        .map(|(name, bits)| {
+            let dir = bits[0].1.clone();
+            let upto = graph.upto.contains(&name);
+            let mut bits: Vec<Net> = bits.into_iter().map(|(net, _)| net).collect();
+            if upto {
+                bits.reverse();
+            }
+            let modport = ModPort { bits, upto };
+            let port = match dir {
+                PortDirection::Input => Port::Input(modport),
+                PortDirection::Output => Port::Output(modport),
+                PortDirection::Inout => Port::InOut(modport),
+            };
+            (name, port)
+        })
+        .collect();
+
+        let netnames = group_bits(
+            graph
+                .netnames
+                .iter()
+                .map(|(net, name)| (name.clone(), net.clone()))
+                .collect(),
+        )
+        .into_iter()
+        .map(|(name, mut bits)| {
+            let hide_name = name.starts_with('$');
+            let upto = graph.upto.contains(&name);
+            if upto {
+                bits.reverse();
+            }
+            let mut attributes = HashMap::new();
+            if bits.iter().any(|net| graph.inits.contains_key(net)) {
+                let init = bits
+                    .iter()
+                    .rev()
+                    .map(|net| match graph.inits.get(net) {
+                        Some(true) => '1',
+                        Some(false) => '0',
+                        None => 'x',
+                    })
+                    .collect();
+                attributes.insert("init".to_string(), init);
+            }
+            (
+                name,
+                NetName {
+                    hide_name,
+                    bits,
+                    upto,
+                    attributes,
+                },
+            )
+        })
+        .collect();
+
+        YosysDoc {
+            creator: format!("ver2gal {}", env!("CARGO_PKG_VERSION")),
+            modules: HashMap::from([(
+                mod_name,
+                Module {
+                    ports,
+                    cells,
+                    netnames,
+                },
+            )]),
+        }
+    }
+}
+
 pub enum CellType {
     Input,
     Sop,
@@ -639,10 +1187,90 @@ mod tests {
         Ok(())
     }
     #[test]
+    fn test_group_bits() {
+        let named = vec![
+            ("io[1]".to_string(), 5),
+            ("io[0]".to_string(), 4),
+            ("clk".to_string(), 2),
+            ("gap[1]".to_string(), 7),
+        ];
+        let mut grouped = group_bits(named);
+        grouped.sort();
+        assert_eq!(
+            grouped,
+            vec![
+                ("clk".to_string(), vec![2]),
+                ("gap[1]".to_string(), vec![7]),
+                ("io".to_string(), vec![4, 5]),
+            ]
+        );
+    }
+    #[test]
+    fn test_parse_init() {
+        let bits = [Net::N(2), Net::N(3), Net::N(4), Net::LiteralZero];
+        // the last character is bit 0.
+        assert_eq!(
+            parse_init("01x1", &bits),
+            vec![(Net::N(2), true), (Net::N(4), true)]
+        );
+    }
+    #[test]
     fn test_netspecial_one() -> Result<()> {
         let netstring = "\"1\"";
         let data: Net = from_str(netstring)?;
         assert_eq!(data, Net::LiteralOne);
         Ok(())
     }
+    #[test]
+    fn test_get_other_same_node() {
+        let adj = NetAdjPair {
+            net: Net::N(3),
+            idx1: NodeIdx(1),
+            port1: "A".to_string(),
+            idx2: NodeIdx(1),
+            port2: "E".to_string(),
+        };
+        assert_eq!(adj.get_other(&NodeIdx(1), "A"), Some((NodeIdx(1), "E")));
+        assert_eq!(adj.get_other(&NodeIdx(1), "E"), Some((NodeIdx(1), "A")));
+        assert_eq!(adj.get_other(&NodeIdx(1), "Y"), None);
+        assert_eq!(adj.get_other(&NodeIdx(2), "A"), None);
+    }
+    #[test]
+    fn test_round_trip_cell_types() -> Result<()> {
+        let text = r#"{
+            "creator": "test",
+            "modules": {
+                "top": {
+                    "ports": {
+                        "a": {"direction": "input", "bits": [2, 3], "upto": 1},
+                        "y": {"direction": "output", "bits": [4]}
+                    },
+                    "cells": {
+                        "one": {
+                            "type": "GAL_1SOP",
+                            "parameters": {
+                                "DEPTH": "1",
+                                "TABLE": "1010",
+                                "WIDTH": "10"
+                            },
+                            "connections": {"A": [2, 3], "Y": [4]}
+                        }
+                    }
+                }
+            }
+        }"#;
+        let g = Graph::from(from_str::<YosysDoc>(text)?);
+        assert!(g.upto.contains("a"));
+        assert!(g.one_sops.contains("top/one"));
+        // yosys lists the bits LSB first, which for an upto port is the highest index.
+        let a1 = g.ports.iter().find(|p| p.name == "a[1]").unwrap();
+        assert_eq!(a1.net, Net::N(2));
+
+        let doc = YosysDoc::from(&g);
+        let module = &doc.modules["top"];
+        assert!(matches!(module.cells["one"], YosysCell::OneSop(_)));
+        assert!(matches!(&module.ports["a"], Port::Input(p) if p.upto && p.bits == [Net::N(2), Net::N(3)]));
+        assert!(matches!(&module.ports["y"], Port::Output(p) if !p.upto));
+        Ok(())
+    }
 }
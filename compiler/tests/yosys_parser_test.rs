@@ -21,6 +21,111 @@ fn test_graph() -> Result<(), Box<dyn Error>> {
 
     let g = Graph::from(data);
     println!("{:?}", g);
-    g.validate()?;
+    // the registered NAND is fed straight from another OLMC, which needs a SOP in between.
+    let err = g.validate().unwrap_err();
+    assert_eq!(err.0.len(), 1, "{err}");
+    assert_eq!(err.0[0].message, "no SOP drives the A input");
+    Ok(())
+}
+
+#[test]
+fn test_drivers_and_sinks() -> Result<(), Box<dyn Error>> {
+    let f = fs::read("testcases/json/synth_olmc_test.json")?;
+
+    let data: YosysDoc = from_slice(f.as_slice())?;
+
+    let g = Graph::from(data);
+    // the SOP output feeds three OLMCs.
+    let and = Net::N(9);
+    assert_eq!(g.net_name(&and), "$iopadmap$AND");
+    let drivers = g.drivers(&and);
+    assert_eq!(drivers.len(), 1);
+    assert_eq!(drivers[0].port, "Y");
+    assert!(matches!(g.get_node(&drivers[0].node), Some(Node::Sop(_))));
+    assert_eq!(g.sinks(&and).len(), 3);
+
+    // NAND is driven by an OLMC, and read back by another one.
+    let nand = Net::N(6);
+    assert_eq!(g.net_name(&nand), "NAND");
+    assert_eq!(g.drivers(&nand)[0].direction, PortDirection::Inout);
+    assert_eq!(g.sinks(&nand).len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_validate_multiple_drivers() -> Result<(), Box<dyn Error>> {
+    let f = fs::read("testcases/json/synth_olmc_test.json")?;
+
+    let data: YosysDoc = from_slice(f.as_slice())?;
+
+    let mut g = Graph::from(data);
+    // tie the A input buffer onto the B net too.
+    for node in g.nodelist.iter_mut() {
+        if let Node::Input(i) = node {
+            if i.connections.get("Y") == Some(&vec![Net::N(11)]) {
+                i.connections.insert("Y".to_string(), vec![Net::N(10)]);
+            }
+        }
+    }
+    g.generate_adjacency();
+    let err = g.validate().unwrap_err().to_string();
+    assert!(err.contains("net $abc$98$iopadmap$B: has multiple drivers"), "{err}");
+    assert!(err.contains("net $abc$98$iopadmap$A: is undriven"), "{err}");
+    Ok(())
+}
+
+#[test]
+fn test_validate_sop_problems() -> Result<(), Box<dyn Error>> {
+    let f = fs::read("testcases/json/synth_olmc_test.json")?;
+
+    let data: YosysDoc = from_slice(f.as_slice())?;
+
+    let mut g = Graph::from(data);
+    for node in g.nodelist.iter_mut() {
+        if let Node::Sop(s) = node {
+            // feed the AND gate its own output, and leave another input floating.
+            let a = s.connections.get_mut("A").unwrap();
+            a[0] = Net::N(9);
+            a[1] = Net::NotConnected;
+            a.push(Net::LiteralZero);
+            s.parameters.depth = 2;
+        }
+    }
+    g.generate_adjacency();
+    let err = g.validate().unwrap_err().to_string();
+    assert!(err.contains("combinational loop"), "{err}");
+    assert!(err.contains("unconnected (x) net used as an input"), "{err}");
+    assert!(err.contains("but WIDTH is"), "{err}");
+    assert!(err.contains("expected 2*WIDTH*DEPTH"), "{err}");
+    Ok(())
+}
+
+#[test]
+fn test_round_trip() -> Result<(), Box<dyn Error>> {
+    let f = fs::read("testcases/json/synth_olmc_test.json")?;
+
+    let data: YosysDoc = from_slice(f.as_slice())?;
+    let g = Graph::from(data);
+
+    let text = serde_json::to_string(&YosysDoc::from(&g))?;
+    // parameters keep yosys's binary string encoding.
+    assert!(text.contains("\"WIDTH\":\"00000000000000000000000000000010\""), "{text}");
+    let back = Graph::from(serde_json::from_str::<YosysDoc>(&text)?);
+
+    let mut names: Vec<String> = g.nodelist.iter().map(|n| n.name()).collect();
+    let mut back_names: Vec<String> = back.nodelist.iter().map(|n| n.name()).collect();
+    names.sort();
+    back_names.sort();
+    assert_eq!(names, back_names);
+    for node in &g.nodelist {
+        let other = back.nodelist.iter().find(|n| n.name() == node.name()).unwrap();
+        assert_eq!(node, other);
+    }
+    let mut ports = g.ports.clone();
+    let mut back_ports = back.ports.clone();
+    ports.sort_by(|a, b| a.name.cmp(&b.name));
+    back_ports.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(ports, back_ports);
+    assert_eq!(g.netnames, back.netnames);
     Ok(())
 }
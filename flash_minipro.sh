@@ -8,27 +8,16 @@ if [[ $# -ne 2 ]]; then
 	exit 1
 fi
 
-# Program GAL
-minipro -p "$2" -w "$1"
-
-# Read GAL
-READBACK=/tmp/gal_tmp
-minipro -p "$2" -r "$READBACK.jed"
-
-# Fix JEDEC for jedutil
-truncate -s -7 "$READBACK.jed"
-printf "*\r\n\x03" >> "$READBACK.jed"
-od -t u1 -An -w1 -v "$READBACK.jed" | awk '{s+=$1; if(s > 65535) s = and(65535, s)} END {printf("%04X\r\n", s)}' >> "$READBACK.jed"
-
-# Convert to binary and compare
-jedutil -convert "$READBACK.jed" "$READBACK.bin"
-jedutil -convert "$1" "${1%.jed}.bin"
-
-if cmp -s "$READBACK.bin" "${1%.jed}.bin"; then
-	echo "VERIFICATION OK!"
-else
-	echo "VERIFICATION FAILED!"
-fi
-
-# Clean up
-rm "$READBACK.jed" "$READBACK.bin" "${1%.jed}.bin"
+# Find ver2gal next to this script, as the README runs it, then in the build
+# directory, then on the PATH
+dir="$(cd "$(dirname "$0")" && pwd)"
+ver2gal=ver2gal
+for candidate in "$dir/ver2gal" "$dir/compiler/target/release/ver2gal" "$dir/compiler/target/debug/ver2gal"; do
+	if [[ -x "$candidate" ]]; then
+		ver2gal="$candidate"
+		break
+	fi
+done
+
+# Program the GAL, read it back and verify it
+exec "$ver2gal" program "$1" --chip "$2" --programmer minipro
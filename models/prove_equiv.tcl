@@ -3,34 +3,45 @@ yosys -import
 
 # Parse arguments
 if {$argc < 3} {
-	puts "USAGE: $argv0 -- <JEDEC_FILE> <PCF_FILE> <VERILOG FILES> ..."
+	puts "USAGE: $argv0 -- <JEDEC_FILE or HEX FUSE MAP> <PCF_FILE> <VERILOG FILES> ..."
 	exit
 }
 
-set jedec_file [lindex $argv 0]
+set fuse_file [lindex $argv 0]
 set pcf_file [lindex $argv 1]
 set verilog_files [lrange $argv 2 end]
 
-# Convert JEDEC file to bin
-exec jedutil -convert $jedec_file __temp.bin
+# Convert a JEDEC file to a hex fuse map, one byte per line
+if {[string tolower [file extension $fuse_file]] == ".jed"} {
+	exec jedutil -convert $fuse_file __temp.bin
+	exec xxd -ps -c 1 __temp.bin __temp.hex
+	exec rm __temp.bin
+	set hex_file __temp.hex
+} else {
+	set hex_file $fuse_file
+}
 
-# Find chip being used
-set jedec_bin_size [file size __temp.bin]
-if {$jedec_bin_size == 279} {
+# Find chip being used from the number of bytes
+set hex_fp [open $hex_file r]
+set hex_size [llength [split [string trim [read $hex_fp]] "\n"]]
+close $hex_fp
+if {$hex_size == 279} {
 	set chip GAL16V8
 	set pin_mapping [dict create 1 "clk" 2 "in\[0\]" 3 "in\[1\]" 4 "in\[2\]" 5 "in\[3\]" 6 "in\[4\]" 7 "in\[5\]" 8 "in\[6\]" 9 "in\[7\]" 11 "oe_n" 12 "io\[7\]" 13 "io\[6\]" 14 "io\[5\]" 15 "io\[4\]" 16 "io\[3\]" 17 "io\[2\]" 18 "io\[1\]" 19 "io\[0\]"]
-} elseif {$jedec_bin_size == 741} {
+} elseif {$hex_size == 741} {
 	set chip GAL22V10
 	set pin_mapping [dict create 1 "in\[0\]" 2 "in\[1\]" 3 "in\[2\]" 4 "in\[3\]" 5 "in\[4\]" 6 "in\[5\]" 7 "in\[6\]" 8 "in\[7\]" 9 "in\[8\]" 10 "in\[9\]" 11 "in\[10\]" 12 "in\[11\]" 13 "in\[12\]" 14 "io\[9\]" 15 "io\[8\]" 16 "io\[7\]" 17 "io\[6\]" 18 "io\[5\]" 19 "io\[4\]" 20 "io\[3\]" 21 "io\[2\]" 22 "io\[1\]" 23 "io\[0\]"]
 } else {
-	puts "Error: Unknown chip for JEDEC file"
+	puts "Error: Unknown chip for fuse map"
 	exit
 }
 puts "Chip found to be $chip"
 
-# Convert binary JEDEC file to hex for Verilog
-exec xxd -ps -c 1 __temp.bin ${chip}_reg.hex
-exec rm __temp.bin
+# The model reads its fuse map from here
+file copy -force $hex_file ${chip}_reg.hex
+if {$hex_file == "__temp.hex"} {
+	exec rm __temp.hex
+}
 
 # Read and synthesize original Verilog
 read_verilog $verilog_files
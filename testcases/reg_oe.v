@@ -0,0 +1,17 @@
+module reg_oe (
+	input clk, oe_n,
+	input a, b,
+	output q, r
+);
+
+reg x, y;
+
+assign q = !oe_n ? x : 1'bz;
+assign r = !oe_n ? y : 1'bz;
+
+always @ (posedge clk) begin
+	x <= a && b;
+	y <= a || b;
+end
+
+endmodule